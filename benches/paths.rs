@@ -0,0 +1,161 @@
+//! Benchmark of searching paths between towns on a large high resolution board. Run it with
+//! `cargo bench`.
+//!
+//! Routes between towns on a fixed 1000x1000 board are searched with `path::PathFinder`, which
+//! shares A* search with the generator, and with Dijkstra's algorithm which the generator used
+//! before as the baseline. Each search is repeated and the fastest one is taken to reduce noise.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use world_map_gen::board::{Board, Pos};
+use world_map_gen::gen::RandomBoardGen;
+use world_map_gen::land::LandKind;
+use world_map_gen::path::{land_cost, PathFinder};
+
+const SEED: u64 = 1;
+const SIZE: usize = 1000;
+const REPEAT: usize = 3;
+
+// Previous path search of the generator. Costs of cells are kept in a hash map and routes are kept
+// as linked lists.
+fn dijkstra(board: &Board, from: &Pos, to: &Pos) -> Option<(Vec<Pos>, usize)> {
+    enum Route {
+        Cons(Pos, Rc<Route>),
+        Nil,
+    }
+
+    struct Vert {
+        cost: usize,
+        pos: Pos,
+        prev: Rc<Route>,
+    }
+
+    impl PartialEq for Vert {
+        fn eq(&self, rhs: &Vert) -> bool {
+            self.cost == rhs.cost
+        }
+    }
+    impl Eq for Vert {}
+
+    impl Ord for Vert {
+        fn cmp(&self, rhs: &Vert) -> Ordering {
+            rhs.cost.cmp(&self.cost)
+        }
+    }
+
+    impl PartialOrd for Vert {
+        fn partial_cmp(&self, rhs: &Vert) -> Option<Ordering> {
+            Some(self.cmp(rhs))
+        }
+    }
+
+    let mut costs = HashMap::new();
+    costs.insert(*from, 0);
+    let mut state = BinaryHeap::new();
+    state.push(Vert {
+        cost: 0,
+        pos: *from,
+        prev: Rc::new(Route::Nil),
+    });
+
+    while let Some(Vert { cost, pos, prev }) = state.pop() {
+        if &pos == to {
+            let mut cells = vec![pos];
+            let mut route = &*prev;
+            while let Route::Cons(pos, prev) = route {
+                cells.push(*pos);
+                route = prev;
+            }
+            cells.reverse();
+            return Some((cells, cost));
+        }
+        let prev = Rc::new(Route::Cons(pos, prev));
+        for &(dx, dy) in &[(0, -1), (-1, 0), (1, 0), (0, 1)] {
+            let (x, y) = (pos.x as isize + dx, pos.y as isize + dy);
+            if x < 0 || y < 0 || x as usize >= board.width() || y as usize >= board.height() {
+                continue;
+            }
+            let next = Pos {
+                x: x as usize,
+                y: y as usize,
+            };
+            let cost = cost + land_cost(&board[next]).unwrap();
+            if let Some(c) = costs.get(&next) {
+                if cost >= *c {
+                    continue;
+                }
+            }
+            costs.insert(next, cost);
+            state.push(Vert {
+                cost,
+                pos: next,
+                prev: prev.clone(),
+            });
+        }
+    }
+    None
+}
+
+fn fastest<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut fastest: Option<(T, Duration)> = None;
+    for _ in 0..REPEAT {
+        let start = Instant::now();
+        let ret = f();
+        let elapsed = start.elapsed();
+        match fastest {
+            Some((_, d)) if d <= elapsed => {}
+            _ => fastest = Some((ret, elapsed)),
+        }
+    }
+    fastest.unwrap()
+}
+
+fn main() {
+    let board = RandomBoardGen::from_seed(SEED).gen_large(SIZE, SIZE);
+    let mut towns = board
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.kind == LandKind::Town)
+        .map(|(i, _)| Pos {
+            x: i % SIZE,
+            y: i / SIZE,
+        })
+        .collect::<Vec<_>>();
+    towns.sort_by_key(|p| (p.x, p.y));
+    assert!(towns.len() >= 2, "Too few towns: {:?}", towns);
+
+    let mut finder = PathFinder::new(&board);
+    let mut total_astar = Duration::default();
+    let mut total_dijkstra = Duration::default();
+    for conn in towns.windows(2) {
+        let (from, to) = (&conn[0], &conn[1]);
+        let (route, astar) = fastest(|| finder.find(from, to));
+        let (baseline, dijkstra) = fastest(|| dijkstra(&board, from, to));
+        let route = route.expect("Town is unreachable");
+        let (cells, cost) = baseline.expect("Town is unreachable");
+        assert_eq!(route.cost, cost, "{:?} -> {:?}", from, to);
+        assert_eq!(route.cells.first(), cells.first());
+        assert_eq!(route.cells.last(), cells.last());
+        println!(
+            "{:?} -> {:?}: A* {:?}, Dijkstra {:?} ({} cells)",
+            from,
+            to,
+            astar,
+            dijkstra,
+            route.cells.len(),
+        );
+        total_astar += astar;
+        total_dijkstra += dijkstra;
+    }
+    println!(
+        "{}x{} seed={}: total of {} routes: A* {:?}, Dijkstra {:?}",
+        SIZE,
+        SIZE,
+        SEED,
+        towns.len() - 1,
+        total_astar,
+        total_dijkstra,
+    );
+}
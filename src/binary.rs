@@ -0,0 +1,397 @@
+//! This module provides a compact binary format of boards.
+//!
+//! JSON output of `draw::draw_json()` contains character and color of every cell so it is large
+//! for large boards. The binary format only contains land kind and altitude of each cell with a
+//! small header. Cells can be compressed with run-length encoding or deflate.
+//!
+//! The format consists of the following fields. Integers are in little endian.
+//!
+//! | Size    | Field                                                                   |
+//! |---------|-------------------------------------------------------------------------|
+//! | 4 bytes | Magic number `WMGB`                                                     |
+//! | 1 byte  | Format version. Currently `1`                                           |
+//! | 1 byte  | Compression. `0` for none, `1` for run-length encoding, `2` for deflate |
+//! | 1 byte  | Resolution. `0` for unknown, `1` for low, `2` for middle, `3` for high  |
+//! | 1 byte  | Flags. Bit 0 is set when seed is known, bit 1 for hex layout of cells   |
+//! | 4 bytes | Width                                                                   |
+//! | 4 bytes | Height                                                                  |
+//! | 8 bytes | Seed. `0` when seed is unknown                                          |
+//! | Rest    | Cells from left-top to right-bottom in the compression                  |
+//!
+//! Each cell is 2 bytes; index of its land kind in `LandKind::ALL` and its altitude. With
+//! run-length encoding, cells are encoded as runs. Each run is 3 bytes; the number of repeated
+//! cells in 1..=255 followed by the cell. With deflate, cells are compressed in zlib format.
+//!
+//! Since only kinds and altitudes are stored, cells are restored as preset cells of their kinds.
+//! Metadata of generation other than seed and resolution (`gen::Metadata`) is not stored.
+//!
+//! ```rust
+//! use world_map_gen::binary::{Compression, Header};
+//! use world_map_gen::board::Board;
+//! use world_map_gen::gen::{RandomBoardGen, Resolution};
+//!
+//! let board = RandomBoardGen::from_seed(42).gen_large(200, 200);
+//!
+//! let header = Header {
+//!     resolution: Some(Resolution::High),
+//!     seed: Some(42),
+//!     compression: Compression::Deflate,
+//! };
+//! let mut buf = Vec::<u8>::new();
+//! board.write_binary(&mut buf, &header).unwrap();
+//!
+//! let (board2, header2) = Board::read_binary(buf.as_slice()).unwrap();
+//! assert_eq!(board, board2);
+//! assert_eq!(header, header2);
+//! ```
+
+use flate2;
+
+use crate::board::{Board, Layout};
+use crate::error::{Error, Result};
+use crate::gen::Resolution;
+use crate::land::LandKind;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"WMGB";
+const VERSION: u8 = 1;
+const FLAG_SEED: u8 = 1;
+const FLAG_HEX: u8 = 2;
+// Max length of one run in run-length encoding
+const MAX_RUN: usize = 255;
+
+/// Compression of cells in binary format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Cells are not compressed
+    None,
+    /// Repeated cells are compressed with run-length encoding. It is fast and effective on boards
+    /// which have large areas of the same cells.
+    Rle,
+    /// Cells are compressed with deflate. This is the smallest.
+    Deflate,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Deflate
+    }
+}
+
+/// Header of binary format except for size of a board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Header {
+    /// Resolution used for generating the board if known
+    pub resolution: Option<Resolution>,
+    /// Seed used for generating the board if known
+    pub seed: Option<u64>,
+    /// Compression of cells
+    pub compression: Compression,
+}
+
+fn invalid<T>(msg: impl Into<String>) -> Result<T> {
+    Err(Error::InvalidBinaryFormat(msg.into()))
+}
+
+fn encode_rle(cells: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut cells = cells.chunks(2).peekable();
+    while let Some(cell) = cells.next() {
+        let mut len = 1;
+        while len < MAX_RUN && cells.peek() == Some(&cell) {
+            cells.next();
+            len += 1;
+        }
+        encoded.push(len as u8);
+        encoded.extend_from_slice(cell);
+    }
+    encoded
+}
+
+fn decode_rle(encoded: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    if encoded.len() % 3 != 0 {
+        return invalid("Run-length encoded cells are truncated");
+    }
+    let mut cells = Vec::new();
+    for run in encoded.chunks(3) {
+        if run[0] == 0 {
+            return invalid("Length of run must not be zero");
+        }
+        if cells.len() + run[0] as usize * 2 > max_len {
+            return invalid("Too many run-length encoded cells");
+        }
+        for _ in 0..run[0] {
+            cells.extend_from_slice(&run[1..]);
+        }
+    }
+    Ok(cells)
+}
+
+impl Board {
+    /// Write the board in compact binary format to the writer. Please see the `binary` module
+    /// documentation for the format. When writing to the writer failed, it returns an error.
+    pub fn write_binary<W: Write>(&self, mut writer: W, header: &Header) -> Result<()> {
+        let compression = match header.compression {
+            Compression::None => 0,
+            Compression::Rle => 1,
+            Compression::Deflate => 2,
+        };
+        let resolution = match header.resolution {
+            None => 0,
+            Some(Resolution::Low) => 1,
+            Some(Resolution::Middle) => 2,
+            Some(Resolution::High) => 3,
+        };
+        let mut flags = if header.seed.is_some() { FLAG_SEED } else { 0 };
+        if self.layout() == Layout::Hex {
+            flags |= FLAG_HEX;
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, compression, resolution, flags])?;
+        writer.write_all(&(self.width() as u32).to_le_bytes())?;
+        writer.write_all(&(self.height() as u32).to_le_bytes())?;
+        writer.write_all(&header.seed.unwrap_or(0).to_le_bytes())?;
+
+        let mut cells = Vec::with_capacity(self.width() * self.height() * 2);
+        for cell in self.iter() {
+            cells.push(cell.kind as u8);
+            cells.push(cell.altitude);
+        }
+        match header.compression {
+            Compression::None => writer.write_all(&cells)?,
+            Compression::Rle => writer.write_all(&encode_rle(&cells))?,
+            Compression::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(writer, flate2::Compression::default());
+                encoder.write_all(&cells)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a board in compact binary format from the reader. It returns the board and its header.
+    /// Cells are restored as preset cells of their land kinds. When the input is not in the format
+    /// or reading from the reader failed, it returns an error.
+    pub fn read_binary<R: Read>(mut reader: R) -> Result<(Board, Header)> {
+        let mut head = [0; 24];
+        if reader.read_exact(&mut head).is_err() {
+            return invalid("Header is truncated");
+        }
+        if &head[0..4] != MAGIC {
+            return invalid("Magic number 'WMGB' is not found");
+        }
+        if head[4] != VERSION {
+            return invalid(format!("Unsupported format version {}", head[4]));
+        }
+        let compression = match head[5] {
+            0 => Compression::None,
+            1 => Compression::Rle,
+            2 => Compression::Deflate,
+            c => return invalid(format!("Unknown compression {}", c)),
+        };
+        let resolution = match head[6] {
+            0 => None,
+            1 => Some(Resolution::Low),
+            2 => Some(Resolution::Middle),
+            3 => Some(Resolution::High),
+            r => return invalid(format!("Unknown resolution {}", r)),
+        };
+        let u32_at =
+            |i: usize| u32::from_le_bytes([head[i], head[i + 1], head[i + 2], head[i + 3]]);
+        let width = u32_at(8) as usize;
+        let height = u32_at(12) as usize;
+        let seed = if head[7] & FLAG_SEED != 0 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&head[16..24]);
+            Some(u64::from_le_bytes(bytes))
+        } else {
+            None
+        };
+
+        // Size is taken from untrusted input
+        let expected = match width.checked_mul(height).and_then(|n| n.checked_mul(2)) {
+            Some(n) => n,
+            None => return invalid(format!("Board size {}x{} is too large", width, height)),
+        };
+
+        // Reading is capped by the size of cells so that a small input cannot make a large
+        // allocation. One more byte is read to detect extra cells.
+        let mut payload = Vec::new();
+        match compression {
+            Compression::Deflate => {
+                let decoder = flate2::read::ZlibDecoder::new(reader);
+                let mut decoder = decoder.take(expected as u64 + 1);
+                if let Err(err) = decoder.read_to_end(&mut payload) {
+                    return invalid(format!("Cannot decompress cells: {}", err));
+                }
+            }
+            Compression::Rle => {
+                // Each run contains one cell at least
+                let max_len = (expected / 2).saturating_mul(3).saturating_add(1);
+                reader.take(max_len as u64).read_to_end(&mut payload)?;
+            }
+            Compression::None => {
+                reader.take(expected as u64 + 1).read_to_end(&mut payload)?;
+            }
+        }
+        let cells = match compression {
+            Compression::Rle => decode_rle(&payload, expected)?,
+            _ => payload,
+        };
+
+        if cells.len() != expected {
+            return invalid(format!(
+                "{} cells are required for {}x{} board but got {} bytes",
+                expected / 2,
+                width,
+                height,
+                cells.len(),
+            ));
+        }
+        if let Some(kind) = cells
+            .iter()
+            .step_by(2)
+            .find(|k| **k as usize >= LandKind::ALL.len())
+        {
+            return invalid(format!("Unknown land kind {}", kind));
+        }
+
+        let mut board = Board::build(width, height, |x, y| {
+            let i = (y * width + x) * 2;
+            LandKind::ALL[cells[i] as usize].preset(cells[i + 1])
+        });
+        if head[7] & FLAG_HEX != 0 {
+            board.set_layout(Layout::Hex);
+        }
+        let header = Header {
+            resolution,
+            seed,
+            compression,
+        };
+        Ok((board, header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::RandomBoardGen;
+
+    #[test]
+    fn round_trip() {
+        let mut gen = RandomBoardGen::from_seed(7);
+        for board in &[
+            gen.gen_small(5, 7),
+            gen.gen_middle(60, 40),
+            gen.gen_large(150, 120),
+            Board::build(300, 1, |_, _| LandKind::Sea.preset(3)),
+        ] {
+            let mut sizes = vec![];
+            for compression in &[Compression::None, Compression::Rle, Compression::Deflate] {
+                let header = Header {
+                    resolution: Some(Resolution::Middle),
+                    seed: Some(u64::MAX),
+                    compression: *compression,
+                };
+                let mut buf = Vec::new();
+                board.write_binary(&mut buf, &header).unwrap();
+                let (actual, actual_header) = Board::read_binary(buf.as_slice()).unwrap();
+                assert_eq!(&actual, board);
+                assert_eq!(actual_header, header);
+                sizes.push(buf.len());
+            }
+            assert_eq!(sizes[0], 24 + board.width() * board.height() * 2);
+            if board.width() * board.height() >= 300 {
+                assert!(sizes[2] < sizes[0], "{:?}", sizes);
+            }
+        }
+
+        let mut board = Board::build(2, 2, |x, _| LandKind::ALL[x].preset(50));
+        let mut buf = Vec::new();
+        board.write_binary(&mut buf, &Header::default()).unwrap();
+        let (actual, header) = Board::read_binary(buf.as_slice()).unwrap();
+        assert_eq!(header, Header::default());
+        assert_eq!(actual.layout(), Layout::Square);
+
+        board.set_layout(Layout::Hex);
+        let mut buf = Vec::new();
+        board.write_binary(&mut buf, &Header::default()).unwrap();
+        let (actual, header) = Board::read_binary(buf.as_slice()).unwrap();
+        assert_eq!(header, Header::default());
+        assert_eq!(actual, board);
+    }
+
+    #[test]
+    fn run_length_encoding() {
+        let cells = [1, 2, 1, 2, 1, 2, 3, 4];
+        let encoded = encode_rle(&cells);
+        assert_eq!(encoded, vec![3, 1, 2, 1, 3, 4]);
+        assert_eq!(decode_rle(&encoded, 1200).unwrap(), cells);
+
+        // Long run is split
+        let cells = [5; 600];
+        let encoded = encode_rle(&cells);
+        assert_eq!(encoded, vec![255, 5, 5, 45, 5, 5]);
+        assert_eq!(decode_rle(&encoded, 1200).unwrap(), cells.to_vec());
+    }
+
+    #[test]
+    fn broken_binary() {
+        let mut valid = Vec::new();
+        Board::build(2, 1, |_, _| LandKind::Plain.preset(10))
+            .write_binary(
+                &mut valid,
+                &Header {
+                    compression: Compression::None,
+                    ..Header::default()
+                },
+            )
+            .unwrap();
+        assert!(Board::read_binary(valid.as_slice()).is_ok());
+
+        let broken = |i: usize, b: u8| {
+            let mut v = valid.clone();
+            v[i] = b;
+            v
+        };
+        // Size overflows
+        let mut huge = valid.clone();
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+
+        // Cells more than the size of the board
+        let with_cells = |compression: u8, cells: &[u8]| {
+            let mut v = valid[..24].to_vec();
+            v[5] = compression;
+            v.extend_from_slice(cells);
+            v
+        };
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&vec![0; 1 << 20]).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        for input in &[
+            vec![],
+            valid[..10].to_vec(),
+            valid[..valid.len() - 1].to_vec(),
+            broken(0, b'X'),
+            broken(4, 2),
+            broken(5, 9),
+            broken(6, 9),
+            broken(8, 3),
+            broken(24, 200),
+            broken(5, 1),
+            broken(5, 2),
+            huge.clone(),
+            with_cells(1, &[255, 0, 10]),
+            with_cells(2, &deflated),
+        ] {
+            match Board::read_binary(input.as_slice()) {
+                Err(Error::InvalidBinaryFormat(_)) => {}
+                r => panic!("Unexpected result {:?} for {:?}", r, input),
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! This module provides a table to classify cells into land kinds by their altitudes.
+//!
+//! Each resolution of random map generator has its default table. By replacing the table with
+//! `gen::GenConfig`, you can generate wetter or more mountainous worlds.
+//!
+//! ```rust
+//! use world_map_gen::biome::BiomeTable;
+//! use world_map_gen::gen::{GenConfig, Resolution};
+//! use world_map_gen::LandKind;
+//!
+//! // Default table for middle resolution
+//! let table = BiomeTable::preset(Resolution::Middle);
+//! assert_eq!(table.land_kind(5), LandKind::Sea);
+//! assert_eq!(table.land_kind(80), LandKind::Mountain);
+//!
+//! // Define a wetter table. Each band is a pair of max altitude (inclusive) and land kind.
+//! let table = BiomeTable::new(vec![
+//!     (50, LandKind::Sea),
+//!     (80, LandKind::Plain),
+//!     (99, LandKind::Forest),
+//! ]);
+//! assert_eq!(table.land_kind(50), LandKind::Sea);
+//! assert_eq!(table.land_kind(51), LandKind::Plain);
+//!
+//! let config = GenConfig {
+//!     biomes: Some(table),
+//!     ..GenConfig::default()
+//! };
+//! ```
+
+use crate::gen::Resolution;
+use crate::land::LandKind;
+use std::cmp;
+
+/// A table which maps ranges of altitude to land kinds. It consists of bands. Each band is a pair
+/// of max altitude (inclusive) and land kind. Bands are sorted by their max altitudes. A cell is
+/// classified into the kind of the first band whose max altitude is equal to or larger than the
+/// cell's altitude. Altitudes larger than the last band are classified into the last band's kind.
+/// Empty table classifies all cells into `LandKind::Plain`.
+///
+/// This struct is serializable with serde as an array of `[max_altitude, kind]` pairs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(u8, LandKind)>", into = "Vec<(u8, LandKind)>")]
+pub struct BiomeTable {
+    bands: Vec<(u8, LandKind)>,
+}
+
+impl BiomeTable {
+    /// Creates a new table from bands. Each band is a pair of max altitude (inclusive) and land
+    /// kind. Bands don't need to be sorted.
+    pub fn new(mut bands: Vec<(u8, LandKind)>) -> BiomeTable {
+        bands.sort_by_key(|(max, _)| *max);
+        BiomeTable { bands }
+    }
+
+    /// Returns a default table used for generating a board of the given resolution.
+    pub fn preset(resolution: Resolution) -> BiomeTable {
+        use LandKind::*;
+        let bands = match resolution {
+            Resolution::Low => vec![(15, Sea), (55, Plain), (85, Forest), (99, Mountain)],
+            Resolution::Middle => vec![(10, Sea), (40, Plain), (70, Forest), (99, Mountain)],
+            Resolution::High => vec![
+                (40, DeepSea),
+                (55, Sea),
+                (70, Plain),
+                (80, Forest),
+                (90, Mountain),
+                (99, Highland),
+            ],
+        };
+        BiomeTable { bands }
+    }
+
+    /// Returns bands of the table sorted by their max altitudes.
+    #[inline]
+    pub fn bands(&self) -> &[(u8, LandKind)] {
+        &self.bands
+    }
+
+    /// Returns the max altitude of water bands at the bottom of the table. Cells whose altitudes
+    /// are lower than or equal to the sea level are classified into water such as sea. When the
+    /// lowest band is not water, this method returns `None`.
+    pub fn sea_level(&self) -> Option<u8> {
+        self.bands
+            .iter()
+            .take_while(|(_, kind)| kind.is_water())
+            .last()
+            .map(|(max, _)| *max)
+    }
+
+    /// Creates a new table by shifting water bands so that the sea level becomes the given
+    /// altitude. Water bands are scaled into `0..=sea_level` and other bands are scaled into
+    /// `sea_level+1..=99` keeping their proportions. When the table has no water band, a band of
+    /// `LandKind::Sea` is added to the bottom.
+    pub fn with_sea_level(&self, sea_level: u8) -> BiomeTable {
+        const TOP: u32 = 99;
+        let new_level = u32::from(cmp::min(sea_level, TOP as u8));
+        let old_level = match self.sea_level() {
+            Some(l) => u32::from(l),
+            None => {
+                let mut bands = Vec::with_capacity(self.bands.len() + 1);
+                bands.push((new_level, LandKind::Sea));
+                bands.extend(self.bands.iter().map(|(max, kind)| {
+                    let max = u32::from(*max);
+                    (new_level + 1 + max * (TOP - new_level) / TOP, *kind)
+                }));
+                return BiomeTable::new(bands.into_iter().map(clamp_band).collect());
+            }
+        };
+
+        let bands = self
+            .bands
+            .iter()
+            .map(|(max, kind)| {
+                let max = u32::from(*max);
+                let max = if max <= old_level {
+                    (max * new_level)
+                        .checked_div(old_level)
+                        .unwrap_or(new_level)
+                } else if old_level >= TOP {
+                    TOP
+                } else {
+                    new_level + (max - old_level) * (TOP - new_level) / (TOP - old_level)
+                };
+                (max, *kind)
+            })
+            .map(clamp_band)
+            .collect();
+        BiomeTable::new(bands)
+    }
+
+    /// Classifies the given altitude into land kind.
+    pub fn land_kind(&self, altitude: u8) -> LandKind {
+        self.bands
+            .iter()
+            .find(|(max, _)| altitude <= *max)
+            .or_else(|| self.bands.last())
+            .map(|(_, kind)| *kind)
+            .unwrap_or(LandKind::Plain)
+    }
+}
+
+#[inline]
+fn clamp_band((max, kind): (u32, LandKind)) -> (u8, LandKind) {
+    (cmp::min(max, 255) as u8, kind)
+}
+
+/// Calculates a sea level which makes the ratio of water cells closest to the given ratio from the
+/// histogram of the given altitudes. The ratio is clamped into `0.0..=1.0`. Note that cells at
+/// altitude 0 are always water since a sea level is inclusive.
+///
+/// ```rust
+/// use world_map_gen::biome::sea_level_for_water_ratio;
+///
+/// let altitudes: Vec<u8> = (0..100).collect();
+/// assert_eq!(sea_level_for_water_ratio(altitudes.iter().cloned(), 0.3), 29);
+/// ```
+pub fn sea_level_for_water_ratio<I>(altitudes: I, ratio: f64) -> u8
+where
+    I: IntoIterator<Item = u8>,
+{
+    let mut histogram = [0usize; 256];
+    let mut total = 0;
+    for alt in altitudes {
+        histogram[alt as usize] += 1;
+        total += 1;
+    }
+
+    // On wasm32, usize has 32bits length so converting from usize to f64 is lossless.
+    #[allow(clippy::cast_lossless)]
+    let target = total as f64 * ratio.clamp(0.0, 1.0);
+    let mut level = 0;
+    let mut best = f64::INFINITY;
+    let mut water = 0;
+    for (alt, count) in histogram.iter().enumerate() {
+        water += count;
+        #[allow(clippy::cast_lossless)]
+        let diff = (water as f64 - target).abs();
+        if diff < best {
+            best = diff;
+            level = alt as u8;
+        }
+        if water == total {
+            break;
+        }
+    }
+    level
+}
+
+impl From<Vec<(u8, LandKind)>> for BiomeTable {
+    fn from(bands: Vec<(u8, LandKind)>) -> BiomeTable {
+        BiomeTable::new(bands)
+    }
+}
+
+impl From<BiomeTable> for Vec<(u8, LandKind)> {
+    fn from(table: BiomeTable) -> Vec<(u8, LandKind)> {
+        table.bands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LandKind::*;
+
+    #[test]
+    fn classify_by_presets() {
+        let t = BiomeTable::preset(Resolution::High);
+        for (alt, kind) in &[
+            (0, DeepSea),
+            (40, DeepSea),
+            (41, Sea),
+            (56, Plain),
+            (80, Forest),
+            (81, Mountain),
+            (99, Highland),
+        ] {
+            assert_eq!(t.land_kind(*alt), *kind, "altitude {}", alt);
+        }
+    }
+
+    #[test]
+    fn unsorted_and_out_of_range_bands() {
+        let t = BiomeTable::new(vec![(60, Forest), (20, Sea)]);
+        assert_eq!(t.bands(), &[(20, Sea), (60, Forest)]);
+        assert_eq!(t.land_kind(20), Sea);
+        assert_eq!(t.land_kind(21), Forest);
+        assert_eq!(t.land_kind(99), Forest);
+        assert_eq!(BiomeTable::new(vec![]).land_kind(10), Plain);
+    }
+
+    #[test]
+    fn shift_sea_level() {
+        let t = BiomeTable::preset(Resolution::High);
+        assert_eq!(t.sea_level(), Some(55));
+
+        let shifted = t.with_sea_level(70);
+        assert_eq!(shifted.sea_level(), Some(70));
+        assert_eq!(shifted.land_kind(70), Sea);
+        assert_eq!(shifted.land_kind(71), Plain);
+        assert_eq!(shifted.land_kind(99), Highland);
+        let kinds = shifted.bands().iter().map(|(_, k)| *k).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![DeepSea, Sea, Plain, Forest, Mountain, Highland]);
+        for w in shifted.bands().windows(2) {
+            assert!(w[0].0 < w[1].0, "{:?}", shifted);
+        }
+
+        let lowered = t.with_sea_level(20);
+        assert_eq!(lowered.sea_level(), Some(20));
+        assert_eq!(lowered.land_kind(21), Plain);
+    }
+
+    #[test]
+    fn shift_sea_level_without_water() {
+        let t = BiomeTable::new(vec![(50, Plain), (99, Mountain)]);
+        assert_eq!(t.sea_level(), None);
+        let t = t.with_sea_level(30);
+        assert_eq!(t.sea_level(), Some(30));
+        assert_eq!(t.land_kind(31), Plain);
+        assert_eq!(t.land_kind(99), Mountain);
+    }
+
+    #[test]
+    fn water_ratio_from_histogram() {
+        let alts = vec![0, 0, 10, 10, 20, 30, 40, 50, 60, 70];
+        assert_eq!(sea_level_for_water_ratio(alts.iter().cloned(), 0.0), 0);
+        assert_eq!(sea_level_for_water_ratio(alts.iter().cloned(), 0.4), 10);
+        assert_eq!(sea_level_for_water_ratio(alts.iter().cloned(), 0.5), 20);
+        assert_eq!(sea_level_for_water_ratio(alts.iter().cloned(), 1.0), 70);
+        assert_eq!(sea_level_for_water_ratio(alts.iter().cloned(), 2.0), 70);
+        assert_eq!(sea_level_for_water_ratio(vec![], 0.5), 0);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let t: BiomeTable = serde_json::from_str(r#"[[99, "Forest"], [30, "Sea"]]"#).unwrap();
+        assert_eq!(t, BiomeTable::new(vec![(30, Sea), (99, Forest)]));
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, r#"[[30,"Sea"],[99,"Forest"]]"#);
+    }
+}
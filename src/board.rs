@@ -16,10 +16,22 @@
 //!  V
 //!  y
 //! ```
+//!
+//! Cells are squares by default. On a board in hex layout (`Layout::Hex`), cells are pointy-topped
+//! hexagons and odd rows are shifted right by half a cell. Cells are still addressed by (x, y) in
+//! the same way.
+//!
+//! ```text,ignore
+//!  (0,0) (1,0) (2,0)
+//!     (0,1) (1,1) (2,1)
+//!  (0,2) (1,2) (2,2)
+//! ```
 
 use serde;
 
-use crate::land::Land;
+use crate::color;
+use crate::gen::Metadata;
+use crate::land::{Land, LandKind};
 use std::collections::HashMap;
 use std::ops::{Index, IndexMut};
 use std::slice;
@@ -39,7 +51,8 @@ pub struct Pos {
 #[allow(clippy::trivially_copy_pass_by_ref)]
 impl Pos {
     /// Calculate how much cost it takes to reach other position from self position. Only 4
-    /// directions (up, down, left, right) are allowed to move.
+    /// directions (up, down, left, right) are allowed to move. Please use
+    /// `Neighborhood::move_cost()` to allow diagonal moves.
     #[inline]
     pub fn move_cost(&self, other: &Pos) -> usize {
         use std::cmp::{max, min};
@@ -49,22 +62,284 @@ impl Pos {
     }
 }
 
+/// How a board wraps around at its edges. On a wrapped board, cells at the opposite edges are
+/// adjacent so that the board can be tiled seamlessly like a map of a globe.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Wrap {
+    /// Edges are not connected. This is the default.
+    None,
+    /// The left edge and the right edge are connected like a cylinder.
+    Horizontal,
+    /// Both the left and right edges and the top and bottom edges are connected like a torus.
+    Both,
+}
+
+impl Default for Wrap {
+    fn default() -> Self {
+        Wrap::None
+    }
+}
+
+// Offset and distance on one axis of the given length
+#[inline]
+fn offset_on_axis(v: usize, d: isize, len: usize, wrap: bool) -> Option<usize> {
+    let v = v as isize + d;
+    if wrap && len > 0 {
+        Some(v.rem_euclid(len as isize) as usize)
+    } else if 0 <= v && v < len as isize {
+        Some(v as usize)
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn delta_on_axis(from: usize, to: usize, len: usize, wrap: bool) -> isize {
+    let d = to as isize - from as isize;
+    let len = len as isize;
+    if wrap && d.abs() * 2 > len {
+        d - d.signum() * len
+    } else {
+        d
+    }
+}
+
+impl Wrap {
+    /// Returns true when the left edge and the right edge are connected.
+    #[inline]
+    pub fn horizontal(self) -> bool {
+        self != Wrap::None
+    }
+
+    /// Returns true when the top edge and the bottom edge are connected.
+    #[inline]
+    pub fn vertical(self) -> bool {
+        self == Wrap::Both
+    }
+
+    /// Returns the position moved by (dx, dy) from the given position on a `width * height`
+    /// board. When the moved position goes out of the board on an axis which does not wrap, it
+    /// returns `None`.
+    #[inline]
+    pub fn offset(
+        self,
+        pos: &Pos,
+        dx: isize,
+        dy: isize,
+        width: usize,
+        height: usize,
+    ) -> Option<Pos> {
+        let x = offset_on_axis(pos.x, dx, width, self.horizontal())?;
+        let y = offset_on_axis(pos.y, dy, height, self.vertical())?;
+        Some(Pos { x, y })
+    }
+
+    /// Returns the shortest displacement (dx, dy) from `from` to `to` on a `width * height` board.
+    /// Moving across connected edges is considered.
+    #[inline]
+    pub fn delta(self, from: &Pos, to: &Pos, width: usize, height: usize) -> (isize, isize) {
+        (
+            delta_on_axis(from.x, to.x, width, self.horizontal()),
+            delta_on_axis(from.y, to.y, height, self.vertical()),
+        )
+    }
+
+    /// Calculate how much cost it takes to reach `to` from `from` on a `width * height` board.
+    /// Only 4 directions are allowed to move as `Pos::move_cost()` but moving across connected
+    /// edges is considered.
+    #[inline]
+    pub fn move_cost(self, from: &Pos, to: &Pos, width: usize, height: usize) -> usize {
+        let (dx, dy) = self.delta(from, to, width, height);
+        dx.unsigned_abs() + dy.unsigned_abs()
+    }
+}
+
+/// Neighborhood of a cell on square layout, which is a set of cells to move to in one step.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Neighborhood {
+    /// Only 4 directions (up, down, left, right) are allowed to move. This is the default.
+    Four,
+    /// Diagonal directions are also allowed to move in addition to the 4 directions.
+    Eight,
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Neighborhood::Four
+    }
+}
+
+const SQUARE_DIRS: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+const SQUARE_DIAGONAL_DIRS: [(isize, isize); 8] = [
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (0, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+impl Neighborhood {
+    /// Returns offsets (dx, dy) to the neighbors. Up, left, right and down come first and
+    /// diagonal directions follow them with `Neighborhood::Eight`.
+    #[inline]
+    pub fn directions(self) -> &'static [(isize, isize)] {
+        match self {
+            Neighborhood::Four => &SQUARE_DIRS,
+            Neighborhood::Eight => &SQUARE_DIAGONAL_DIRS,
+        }
+    }
+
+    /// Calculate how much cost it takes to reach `to` from `from`. The cost is the number of
+    /// steps. It is Manhattan distance as `Pos::move_cost()` with `Neighborhood::Four` and
+    /// Chebyshev distance with `Neighborhood::Eight`.
+    #[inline]
+    pub fn move_cost(self, from: &Pos, to: &Pos) -> usize {
+        let dx = (to.x as isize - from.x as isize).unsigned_abs();
+        let dy = (to.y as isize - from.y as isize).unsigned_abs();
+        self.steps(dx, dy)
+    }
+
+    #[inline]
+    fn steps(self, dx: usize, dy: usize) -> usize {
+        match self {
+            Neighborhood::Four => dx + dy,
+            Neighborhood::Eight => dx.max(dy),
+        }
+    }
+}
+
+/// Layout of cells on a board.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    /// Square cells. Each cell has 4 neighbors; up, down, left and right. This is the default.
+    Square,
+    /// Pointy-topped hexagonal cells in offset coordinates where odd rows are shifted right by
+    /// half a cell. Each cell has 6 neighbors. When top and bottom edges are connected by
+    /// `Wrap::Both`, height of a board should be even so that rows across the edges are
+    /// staggered.
+    Hex,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Square
+    }
+}
+
+// East, north-east, north-west, west, south-west and south-east of a cell in even or odd rows
+const HEX_EVEN_DIRS: [(isize, isize); 6] = [(1, 0), (0, -1), (-1, -1), (-1, 0), (-1, 1), (0, 1)];
+const HEX_ODD_DIRS: [(isize, isize); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (0, 1), (1, 1)];
+
+impl Layout {
+    /// Returns offsets (dx, dy) from the given position to its neighbors. On square layout, they
+    /// are directions of the neighborhood. On hex layout, the neighborhood is ignored and they are
+    /// east, north-east, north-west, west, south-west and south-east, and depend on whether the
+    /// row is even or odd.
+    #[inline]
+    pub fn directions(self, pos: &Pos, neighborhood: Neighborhood) -> &'static [(isize, isize)] {
+        match self {
+            Layout::Square => neighborhood.directions(),
+            Layout::Hex if pos.y & 1 == 0 => &HEX_EVEN_DIRS,
+            Layout::Hex => &HEX_ODD_DIRS,
+        }
+    }
+
+    /// Returns an iterator of neighbors of the given position on a `width * height` board which
+    /// wraps around as `wrap`. The neighborhood is only used on square layout. Positions out of
+    /// the board are skipped.
+    #[inline]
+    pub fn neighbors(
+        self,
+        pos: &Pos,
+        wrap: Wrap,
+        neighborhood: Neighborhood,
+        width: usize,
+        height: usize,
+    ) -> impl Iterator<Item = Pos> {
+        let pos = *pos;
+        self.directions(&pos, neighborhood)
+            .iter()
+            .filter_map(move |&(dx, dy)| wrap.offset(&pos, dx, dy, width, height))
+    }
+
+    /// Calculate how much cost it takes to reach `to` from `from` on a `width * height` board which
+    /// wraps around as `wrap`. The cost is the number of steps between neighbors. On square layout
+    /// it is the same as `Neighborhood::move_cost()` except that moving across connected edges is
+    /// considered. On hex layout, the neighborhood is ignored and it is the hex distance.
+    pub fn move_cost(
+        self,
+        from: &Pos,
+        to: &Pos,
+        wrap: Wrap,
+        neighborhood: Neighborhood,
+        width: usize,
+        height: usize,
+    ) -> usize {
+        if self == Layout::Square {
+            let (dx, dy) = wrap.delta(from, to, width, height);
+            return neighborhood.steps(dx.unsigned_abs(), dy.unsigned_abs());
+        }
+
+        // Convert offset coordinates to axial coordinates to calculate distance
+        let axial = |x: isize, y: isize| (x - y.div_euclid(2), y);
+        let (q0, r0) = axial(from.x as isize, from.y as isize);
+        let shifts = |len: usize, wrapped: bool| {
+            let len = len as isize;
+            if wrapped {
+                [-len, 0, len]
+            } else {
+                [0, 0, 0]
+            }
+        };
+        let mut cost = usize::MAX;
+        for &sy in shifts(height, wrap.vertical()).iter() {
+            for &sx in shifts(width, wrap.horizontal()).iter() {
+                let (q1, r1) = axial(to.x as isize + sx, to.y as isize + sy);
+                let (dq, dr) = (q1 - q0, r1 - r0);
+                let d = (dq.unsigned_abs() + dr.unsigned_abs() + (dq + dr).unsigned_abs()) / 2;
+                cost = cost.min(d);
+            }
+        }
+        cost
+    }
+}
+
 /// A struct to represent a one world map. It is generally created by `gen` module's random map
-/// generator. This struct is JSON serializable with `serde_json`.
-#[derive(Debug, PartialEq)]
-pub struct Board<'a> {
+/// generator. This struct is JSON serializable and deserializable with `serde_json`. Boards
+/// generated by the generator have metadata of the generation so that they can be generated again
+/// with `gen::RandomBoardGen::regenerate()`.
+#[derive(Debug)]
+pub struct Board {
     width: usize,
     height: usize,
-    cells: Vec<Land<'a>>,
+    cells: Vec<Land>,
+    layout: Layout,
+    // Boxed to keep the struct small since boards are moved by value while generation
+    metadata: Option<Box<Metadata>>,
 }
 
-impl<'a> Board<'a> {
+impl PartialEq for Board {
+    /// Two boards are equal when they have the same cells in the same layout. Metadata is not
+    /// compared since it only describes how the board was generated.
+    fn eq(&self, rhs: &Board) -> bool {
+        self.width == rhs.width
+            && self.height == rhs.height
+            && self.layout == rhs.layout
+            && self.cells == rhs.cells
+    }
+}
+
+impl Board {
     /// Builds a board with given `width * height` cells. The `builder` generates a cell at the
     /// given position (x, y) by returning `land::Land` instance.
     /// Note that you can use `land::LandKind::preset()` to utilize a preset land instance easily.
-    pub fn build<F>(width: usize, height: usize, mut builder: F) -> Board<'a>
+    /// Cells are in square layout. It can be changed with `Board::set_layout()`.
+    pub fn build<F>(width: usize, height: usize, mut builder: F) -> Board
     where
-        F: FnMut(usize, usize) -> Land<'a>,
+        F: FnMut(usize, usize) -> Land,
     {
         let mut cells = Vec::with_capacity(width * height);
         for y in 0..height {
@@ -76,9 +351,36 @@ impl<'a> Board<'a> {
             cells,
             width,
             height,
+            layout: Layout::Square,
+            metadata: None,
         }
     }
 
+    /// Returns layout of cells
+    #[inline]
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Sets layout of cells. Cells themselves are not changed.
+    #[inline]
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Returns metadata of the generation. It is `None` when the board was not generated by
+    /// `gen::RandomBoardGen` (e.g. built with `Board::build()`).
+    #[inline]
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_deref()
+    }
+
+    /// Sets or clears metadata of the generation.
+    #[inline]
+    pub fn set_metadata(&mut self, metadata: Option<Metadata>) {
+        self.metadata = metadata.map(Box::new);
+    }
+
     /// Returns number of cells per row
     #[inline]
     pub fn width(&self) -> usize {
@@ -98,87 +400,92 @@ impl<'a> Board<'a> {
 
     /// Returns a reference to cell at given (x, y) position
     #[inline]
-    pub fn at(&self, x: usize, y: usize) -> &Land<'_> {
+    pub fn at(&self, x: usize, y: usize) -> &Land {
         &self.cells[self.index_at(x, y)]
     }
 
     /// Returns a mutable reference to cell at given (x, y) position
     #[inline]
-    pub fn at_mut(&mut self, x: usize, y: usize) -> &'a mut Land<'_> {
+    pub fn at_mut(&mut self, x: usize, y: usize) -> &mut Land {
         let idx = self.index_at(x, y);
         &mut self.cells[idx]
     }
 
     /// Returns an iterator which iterates all cells from left-top corner to right-bottom corner
     #[inline]
-    pub fn iter<'b>(&'b self) -> slice::Iter<'b, Land<'a>> {
+    pub fn iter(&self) -> slice::Iter<'_, Land> {
         self.cells.iter()
     }
 
     /// Returns a mutable reference iterator which iterates all cells from left-top corner to right
     /// bottom corner
     #[inline]
-    pub fn iter_mut<'b>(&'b mut self) -> slice::IterMut<'b, Land<'a>> {
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Land> {
         self.cells.iter_mut()
     }
 
     /// Returns an iterator which iterates each row as slice from top to bottom
     #[inline]
-    pub fn rows<'b>(&'b self) -> slice::Chunks<'b, Land<'a>> {
-        self.cells.chunks(self.width)
+    pub fn rows(&self) -> slice::Chunks<'_, Land> {
+        // Chunk size must not be zero even on an empty board
+        self.cells.chunks(self.width.max(1))
     }
 
     /// Returns a mutable reference iterator which iterates each row as slice from top to bottom
     #[inline]
-    pub fn rows_mut<'b>(&'b mut self) -> slice::ChunksMut<'b, Land<'a>> {
-        self.cells.chunks_mut(self.width)
+    pub fn rows_mut(&mut self) -> slice::ChunksMut<'_, Land> {
+        self.cells.chunks_mut(self.width.max(1))
     }
 }
 
-impl<'a> Index<Pos> for Board<'a> {
-    type Output = Land<'a>;
+impl Index<Pos> for Board {
+    type Output = Land;
 
     /// Returns a reference to cell at given position
     #[inline]
-    fn index(&self, p: Pos) -> &Land<'a> {
+    fn index(&self, p: Pos) -> &Land {
         &self.cells[self.index_at(p.x, p.y)]
     }
 }
 
-impl<'a> IndexMut<Pos> for Board<'a> {
+impl IndexMut<Pos> for Board {
     /// Returns a mutable reference to cell at given position
     #[inline]
-    fn index_mut(&mut self, p: Pos) -> &mut Land<'a> {
+    fn index_mut(&mut self, p: Pos) -> &mut Land {
         let idx = self.index_at(p.x, p.y);
         &mut self.cells[idx]
     }
 }
 
-impl<'a> serde::Serialize for Board<'a> {
-    /// Serialize board in a map which contain width, height, cells as array, and legends for each
-    /// land kind as map. By `serde_json`, the struct can be serialized to JSON object.
+impl serde::Serialize for Board {
+    /// Serialize board in a map which contain width, height, layout of cells, cells as array, and
+    /// legends for each land kind as map. Metadata is also contained when the board has it. By
+    /// `serde_json`, the struct can be serialized to JSON object.
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         use serde::ser::SerializeMap;
 
-        struct Cells<'a, 'b: 'a> {
+        struct Cells<'a> {
             w: usize,
             h: usize,
-            vec: &'a Vec<Land<'b>>,
+            vec: &'a Vec<Land>,
         }
-        impl<'a, 'b> serde::Serialize for Cells<'a, 'b> {
+        impl<'a> serde::Serialize for Cells<'a> {
             fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
                 use serde::ser::SerializeSeq;
                 let mut seq = serializer.serialize_seq(Some(self.h))?;
-                for row in self.vec.chunks(self.w) {
-                    seq.serialize_element(row)?;
+                // Rows are sliced by index since chunks cannot be taken when width is zero
+                for y in 0..self.h {
+                    seq.serialize_element(&self.vec[y * self.w..(y + 1) * self.w])?;
                 }
                 seq.end()
             }
         }
 
-        let mut map = serializer.serialize_map(Some(4))?;
+        let len = if self.metadata.is_some() { 6 } else { 5 };
+        let mut map = serializer.serialize_map(Some(len))?;
         map.serialize_entry("width", &self.width)?;
         map.serialize_entry("height", &self.height)?;
+        map.serialize_entry("layout", &self.layout)?;
         map.serialize_entry(
             "cells",
             &Cells {
@@ -195,10 +502,215 @@ impl<'a> serde::Serialize for Board<'a> {
             .collect::<HashMap<_, _>>();
         map.serialize_entry("legends", &legends)?;
 
+        if let Some(metadata) = &self.metadata {
+            map.serialize_entry("metadata", metadata)?;
+        }
+
         map.end()
     }
 }
 
+/// A wrapper of board to serialize it in compact format. Characters and colors of cells are not
+/// repeated for each cell. Instead, `palette` map contains an ID, a character, a color and a
+/// legend for each land kind in the board, and `kinds` and `altitudes` arrays contain IDs of land
+/// kinds and altitudes of cells from left-top to right-bottom. IDs of land kinds are indices in
+/// `land::LandKind::ALL`. Since a palette has one character and one color per land kind, custom
+/// characters and colors of each cell are not preserved. Layout of cells is contained as `layout`
+/// and metadata is contained as `metadata` when the board has it, as well as the full format.
+/// `Board` can be deserialized from this format as well.
+///
+/// ```rust
+/// use world_map_gen::board::{Board, CompactBoard};
+/// use world_map_gen::gen::RandomBoardGen;
+///
+/// let board = RandomBoardGen::default().gen_auto(3, 4);
+/// let json = serde_json::to_string(&CompactBoard(&board)).unwrap();
+/// let board2: Board = serde_json::from_str(&json).unwrap();
+/// assert_eq!(board, board2);
+/// ```
+pub struct CompactBoard<'a>(pub &'a Board);
+
+impl<'a> serde::Serialize for CompactBoard<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        struct Palette<'a>(Vec<&'a Land>);
+        impl<'a> serde::Serialize for Palette<'a> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(self.0.len()))?;
+                for cell in self.0.iter() {
+                    map.serialize_entry(&cell.kind, &PaletteEntry(cell))?;
+                }
+                map.end()
+            }
+        }
+        struct PaletteEntry<'a>(&'a Land);
+        impl<'a> serde::Serialize for PaletteEntry<'a> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("id", &(self.0.kind as u8))?;
+                map.serialize_entry("char", &self.0.char)?;
+                map.serialize_entry("color", &color::SerializableColorSpec(&self.0.color))?;
+                map.serialize_entry("legend", self.0.kind.legend())?;
+                map.end()
+            }
+        }
+
+        let board = self.0;
+        // Palette is ordered by first appearance of land kinds for stable output
+        let mut palette = Vec::new();
+        for cell in board.iter() {
+            if palette.iter().all(|c: &&Land| c.kind != cell.kind) {
+                palette.push(cell);
+            }
+        }
+        let kinds = board.iter().map(|c| c.kind as u8).collect::<Vec<_>>();
+        let altitudes = board.iter().map(|c| c.altitude).collect::<Vec<_>>();
+
+        let len = if board.metadata.is_some() { 7 } else { 6 };
+        let mut map = serializer.serialize_map(Some(len))?;
+        map.serialize_entry("width", &board.width)?;
+        map.serialize_entry("height", &board.height)?;
+        map.serialize_entry("layout", &board.layout)?;
+        map.serialize_entry("palette", &Palette(palette))?;
+        map.serialize_entry("kinds", &kinds)?;
+        map.serialize_entry("altitudes", &altitudes)?;
+        if let Some(metadata) = &board.metadata {
+            map.serialize_entry("metadata", metadata)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Board {
+    /// Deserialize board from a map which `Serialize` implementation or `CompactBoard` outputs.
+    /// Legends are ignored since they can be derived from land kinds of cells. When layout is
+    /// omitted, cells are in square layout.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(Deserialize)]
+        struct PaletteEntry {
+            id: u8,
+            char: String,
+            #[serde(deserialize_with = "color::deserialize_color_spec")]
+            color: termcolor::ColorSpec,
+        }
+
+        #[derive(Deserialize)]
+        struct Repr {
+            width: usize,
+            height: usize,
+            #[serde(default)]
+            layout: Layout,
+            cells: Option<Vec<Vec<Land>>>,
+            palette: Option<HashMap<LandKind, PaletteEntry>>,
+            kinds: Option<Vec<u8>>,
+            altitudes: Option<Vec<u8>>,
+            metadata: Option<Box<Metadata>>,
+        }
+
+        let repr = Repr::deserialize(deserializer)?;
+        let (width, height, layout, metadata) =
+            (repr.width, repr.height, repr.layout, repr.metadata);
+
+        let (palette, kinds, altitudes) =
+            match (repr.cells, repr.palette, repr.kinds, repr.altitudes) {
+                (Some(cells), ..) => {
+                    let mut board = Board::from_rows(width, height, cells)?;
+                    board.layout = layout;
+                    board.metadata = metadata;
+                    return Ok(board);
+                }
+                (None, Some(palette), Some(kinds), Some(altitudes)) => (palette, kinds, altitudes),
+                _ => {
+                    return Err(D::Error::custom(
+                        "board must have 'cells' or all of 'palette', 'kinds' and 'altitudes'",
+                    ))
+                }
+            };
+
+        // Size is taken from untrusted input
+        let num_cells = width.checked_mul(height).ok_or_else(|| {
+            D::Error::custom(format_args!("board size {}x{} is too large", width, height))
+        })?;
+        if kinds.len() != num_cells || altitudes.len() != num_cells {
+            return Err(D::Error::custom(format_args!(
+                "numbers of kinds {} and altitudes {} do not match to {}x{} cells",
+                kinds.len(),
+                altitudes.len(),
+                width,
+                height,
+            )));
+        }
+        let mut lands = HashMap::with_capacity(palette.len());
+        for (kind, entry) in palette.into_iter() {
+            lands.insert(
+                entry.id,
+                Land {
+                    kind,
+                    char: entry.char.into(),
+                    color: entry.color,
+                    altitude: 0,
+                },
+            );
+        }
+        let cells = kinds
+            .into_iter()
+            .zip(altitudes)
+            .map(|(id, altitude)| match lands.get(&id) {
+                Some(land) => Ok(Land {
+                    altitude,
+                    ..land.clone()
+                }),
+                None => Err(D::Error::custom(format_args!(
+                    "land kind ID {} is not in palette",
+                    id
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Board {
+            width,
+            height,
+            cells,
+            layout,
+            metadata,
+        })
+    }
+}
+
+impl Board {
+    fn from_rows<E: serde::de::Error>(
+        width: usize,
+        height: usize,
+        cells: Vec<Vec<Land>>,
+    ) -> Result<Board, E> {
+        if cells.len() != height {
+            return Err(E::custom(format_args!(
+                "number of rows {} does not match to height {}",
+                cells.len(),
+                height,
+            )));
+        }
+        if let Some(row) = cells.iter().find(|row| row.len() != width) {
+            return Err(E::custom(format_args!(
+                "number of cells in row {} does not match to width {}",
+                row.len(),
+                width,
+            )));
+        }
+
+        Ok(Board {
+            width,
+            height,
+            cells: cells.into_iter().flatten().collect(),
+            layout: Layout::Square,
+            metadata: None,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,7 +721,7 @@ mod tests {
     fn build_board() {
         let board = Board::build(2, 3, |x, y| Land {
             kind: LandKind::Town,
-            char: "hi",
+            char: "hi".into(),
             color: ColorSpec::default(),
             altitude: (x + 2 * y) as u8,
         });
@@ -226,6 +738,142 @@ mod tests {
         }
     }
 
+    #[test]
+    fn wrap_offset() {
+        let p = Pos { x: 0, y: 9 };
+        assert_eq!(Wrap::None.offset(&p, -1, 0, 10, 10), None);
+        assert_eq!(
+            Wrap::None.offset(&p, 1, 0, 10, 10),
+            Some(Pos { x: 1, y: 9 })
+        );
+        assert_eq!(
+            Wrap::Horizontal.offset(&p, -1, 0, 10, 10),
+            Some(Pos { x: 9, y: 9 })
+        );
+        assert_eq!(Wrap::Horizontal.offset(&p, 0, 1, 10, 10), None);
+        assert_eq!(
+            Wrap::Both.offset(&p, -1, 1, 10, 10),
+            Some(Pos { x: 9, y: 0 })
+        );
+    }
+
+    #[test]
+    fn wrap_distance() {
+        let (a, b) = (Pos { x: 1, y: 1 }, Pos { x: 8, y: 9 });
+        assert_eq!(Wrap::None.delta(&a, &b, 10, 10), (7, 8));
+        assert_eq!(Wrap::None.move_cost(&a, &b, 10, 10), a.move_cost(&b));
+        assert_eq!(Wrap::Horizontal.delta(&a, &b, 10, 10), (-3, 8));
+        assert_eq!(Wrap::Horizontal.move_cost(&a, &b, 10, 10), 11);
+        assert_eq!(Wrap::Both.delta(&a, &b, 10, 10), (-3, -2));
+        assert_eq!(Wrap::Both.delta(&b, &a, 10, 10), (3, 2));
+        assert_eq!(Wrap::Both.move_cost(&a, &b, 10, 10), 5);
+    }
+
+    #[test]
+    fn hex_neighbors() {
+        let neighbors = |x, y, wrap| {
+            let mut v = Layout::Hex
+                .neighbors(&Pos { x, y }, wrap, Neighborhood::Four, 4, 4)
+                .map(|p| (p.x, p.y))
+                .collect::<Vec<_>>();
+            v.sort();
+            v
+        };
+        let expected = vec![(0, 1), (0, 2), (0, 3), (1, 1), (1, 3), (2, 2)];
+        assert_eq!(neighbors(1, 2, Wrap::None), expected);
+        let expected = vec![(0, 1), (1, 0), (1, 2), (2, 0), (2, 1), (2, 2)];
+        assert_eq!(neighbors(1, 1, Wrap::None), expected);
+        assert_eq!(neighbors(0, 0, Wrap::None), vec![(0, 1), (1, 0)]);
+        let expected = vec![(0, 1), (0, 3), (1, 0), (3, 0), (3, 1), (3, 3)];
+        assert_eq!(neighbors(0, 0, Wrap::Both), expected);
+
+        // Neighbors are symmetric
+        for y in 0..4 {
+            for x in 0..4 {
+                for (nx, ny) in neighbors(x, y, Wrap::Both) {
+                    assert!(neighbors(nx, ny, Wrap::Both).contains(&(x, y)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hex_move_cost() {
+        let cost = |from: (usize, usize), to: (usize, usize), wrap| {
+            let (from, to) = (
+                Pos {
+                    x: from.0,
+                    y: from.1,
+                },
+                Pos { x: to.0, y: to.1 },
+            );
+            Layout::Hex.move_cost(&from, &to, wrap, Neighborhood::Four, 10, 10)
+        };
+        assert_eq!(cost((3, 3), (3, 3), Wrap::None), 0);
+        assert_eq!(cost((3, 3), (4, 4), Wrap::None), 1);
+        assert_eq!(cost((3, 3), (3, 5), Wrap::None), 2);
+        assert_eq!(cost((0, 0), (0, 6), Wrap::None), 6);
+        assert_eq!(cost((0, 0), (3, 6), Wrap::None), 6);
+        assert_eq!(cost((0, 0), (4, 6), Wrap::None), 7);
+        assert_eq!(cost((0, 0), (9, 0), Wrap::None), 9);
+        assert_eq!(cost((0, 0), (9, 0), Wrap::Horizontal), 1);
+        assert_eq!(cost((0, 0), (9, 9), Wrap::Horizontal), 9);
+        assert_eq!(cost((0, 0), (9, 9), Wrap::Both), 1);
+
+        // Square layout is the same as wrap
+        let (a, b) = (Pos { x: 1, y: 1 }, Pos { x: 8, y: 9 });
+        for &wrap in &[Wrap::None, Wrap::Horizontal, Wrap::Both] {
+            let cost = Layout::Square.move_cost(&a, &b, wrap, Neighborhood::Four, 10, 10);
+            assert_eq!(cost, wrap.move_cost(&a, &b, 10, 10));
+        }
+    }
+
+    #[test]
+    fn neighborhood_move_cost() {
+        let (a, b) = (Pos { x: 1, y: 1 }, Pos { x: 8, y: 4 });
+        assert_eq!(Neighborhood::Four.move_cost(&a, &b), a.move_cost(&b));
+        assert_eq!(Neighborhood::Eight.move_cost(&a, &b), 7);
+        assert_eq!(Neighborhood::Eight.move_cost(&b, &a), 7);
+
+        let cost = |wrap, n| Layout::Square.move_cost(&a, &b, wrap, n, 10, 10);
+        assert_eq!(cost(Wrap::None, Neighborhood::Eight), 7);
+        assert_eq!(cost(Wrap::Horizontal, Neighborhood::Four), 6);
+        assert_eq!(cost(Wrap::Horizontal, Neighborhood::Eight), 3);
+        // Neighborhood is ignored on hex layout
+        let hex = |n| Layout::Hex.move_cost(&a, &b, Wrap::None, n, 10, 10);
+        assert_eq!(hex(Neighborhood::Four), hex(Neighborhood::Eight));
+
+        let neighbors = |p, n| Layout::Square.neighbors(&p, Wrap::None, n, 10, 10).count();
+        assert_eq!(neighbors(Pos { x: 0, y: 0 }, Neighborhood::Four), 2);
+        assert_eq!(neighbors(Pos { x: 0, y: 0 }, Neighborhood::Eight), 3);
+        assert_eq!(neighbors(Pos { x: 5, y: 0 }, Neighborhood::Eight), 5);
+        assert_eq!(neighbors(Pos { x: 5, y: 5 }, Neighborhood::Eight), 8);
+    }
+
+    #[test]
+    fn serialize_layout() {
+        let mut board = Board::build(2, 2, |_, _| LandKind::Sea.preset(3));
+        let json = serde_json::to_value(&board).unwrap();
+        assert_eq!(json["layout"], "Square");
+
+        board.set_layout(Layout::Hex);
+        let json = serde_json::to_value(&board).unwrap();
+        assert_eq!(json["layout"], "Hex");
+        let actual: Board = serde_json::from_value(json).unwrap();
+        assert_eq!(actual, board);
+        let json = serde_json::to_value(CompactBoard(&board)).unwrap();
+        assert_eq!(json["layout"], "Hex");
+        let actual: Board = serde_json::from_value(json).unwrap();
+        assert_eq!(actual, board);
+
+        // Layout is square when it is omitted
+        let mut json = serde_json::to_value(&board).unwrap();
+        json.as_object_mut().unwrap().remove("layout");
+        let actual: Board = serde_json::from_value(json).unwrap();
+        assert_eq!(actual.layout(), Layout::Square);
+        assert_ne!(actual, board);
+    }
+
     #[test]
     fn iter_board() {
         let mut idx = 0;
@@ -233,7 +881,7 @@ mod tests {
             idx += 1;
             Land {
                 kind: LandKind::Town,
-                char: "hi",
+                char: "hi".into(),
                 color: ColorSpec::default(),
                 altitude: idx,
             }
@@ -245,6 +893,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn iter_empty_board() {
         let board = Board::build(0, 0, |_, _| Land::default());
         for _ in board.iter() {
@@ -256,7 +905,7 @@ mod tests {
     fn iter_rows() {
         let board = Board::build(2, 3, |x, y| Land {
             kind: LandKind::Town,
-            char: "hi",
+            char: "hi".into(),
             color: ColorSpec::default(),
             altitude: (x + 2 * y) as u8,
         });
@@ -267,7 +916,7 @@ mod tests {
 
         let mut board = Board::build(2, 3, |x, y| Land {
             kind: LandKind::Town,
-            char: "hi",
+            char: "hi".into(),
             color: ColorSpec::default(),
             altitude: (x + 2 * y) as u8,
         });
@@ -276,4 +925,118 @@ mod tests {
         }
         assert_eq!(board.rows_mut().count(), 3);
     }
+
+    #[test]
+    fn deserialize_board() {
+        use crate::gen::RandomBoardGen;
+
+        let mut gen = RandomBoardGen::from_seed(42);
+        for board in &[
+            gen.gen_small(10, 8),
+            gen.gen_middle(40, 30),
+            gen.gen_large(120, 80),
+            Board::build(1, 1, |_, _| Land::default()),
+            Board::build(0, 3, |_, _| Land::default()),
+            Board::build(3, 0, |_, _| Land::default()),
+        ] {
+            let json = serde_json::to_string(board).unwrap();
+            let actual: Board = serde_json::from_str(&json).unwrap();
+            assert_eq!(&actual, board);
+            let json = serde_json::to_string(&CompactBoard(board)).unwrap();
+            let actual: Board = serde_json::from_str(&json).unwrap();
+            assert_eq!(&actual, board);
+        }
+    }
+
+    #[test]
+    fn deserialize_board_with_wrong_size() {
+        let board = Board::build(3, 2, |_, _| LandKind::Plain.preset(10));
+        let mut json = serde_json::to_value(&board).unwrap();
+        json["width"] = 4.into();
+        let err = serde_json::from_str::<Board>(&json.to_string()).unwrap_err();
+        assert!(format!("{}", err).contains("width"), "{}", err);
+
+        json["width"] = 3.into();
+        json["height"] = 3.into();
+        let err = serde_json::from_str::<Board>(&json.to_string()).unwrap_err();
+        assert!(format!("{}", err).contains("height"), "{}", err);
+    }
+
+    #[test]
+    fn compact_json() {
+        let board = Board::build(3, 2, |x, _| {
+            if x == 0 {
+                LandKind::Sea.preset(3)
+            } else {
+                LandKind::Forest.preset(50 + x as u8)
+            }
+        });
+        let json = serde_json::to_value(CompactBoard(&board)).unwrap();
+        assert_eq!(json["kinds"], serde_json::json!([0, 2, 2, 0, 2, 2]));
+        assert_eq!(json["altitudes"], serde_json::json!([3, 51, 52, 3, 51, 52]));
+        assert_eq!(json["palette"]["Forest"]["id"], 2);
+        assert_eq!(json["palette"]["Forest"]["color"]["fg"], "#005f00");
+        assert_eq!(json["palette"].as_object().unwrap().len(), 2);
+
+        use crate::gen::RandomBoardGen;
+
+        let mut gen = RandomBoardGen::from_seed(42);
+        for board in &[gen.gen_small(10, 10), gen.gen_large(200, 200), board] {
+            let compact = serde_json::to_string(&CompactBoard(board)).unwrap();
+            let actual: Board = serde_json::from_str(&compact).unwrap();
+            assert_eq!(&actual, board);
+        }
+
+        let board = gen.gen_large(200, 200);
+        let full = serde_json::to_string(&board).unwrap();
+        let compact = serde_json::to_string(&CompactBoard(&board)).unwrap();
+        assert!(
+            compact.len() * 10 < full.len(),
+            "{} {}",
+            compact.len(),
+            full.len()
+        );
+    }
+
+    #[test]
+    fn deserialize_broken_compact_json() {
+        let board = Board::build(2, 1, |_, _| LandKind::Plain.preset(10));
+        let json = serde_json::to_value(CompactBoard(&board)).unwrap();
+        for (key, value) in &[
+            ("kinds", serde_json::json!([3])),
+            ("altitudes", serde_json::json!([10, 10, 10])),
+            ("kinds", serde_json::json!([3, 4])),
+            ("palette", serde_json::Value::Null),
+        ] {
+            let mut json = json.clone();
+            json[*key] = value.clone();
+            assert!(
+                serde_json::from_str::<Board>(&json.to_string()).is_err(),
+                "{}",
+                json
+            );
+        }
+
+        // Size overflows
+        let json = format!(
+            r#"{{"width":{},"height":2,"palette":{{}},"kinds":[],"altitudes":[]}}"#,
+            1usize << (usize::BITS - 1),
+        );
+        let err = serde_json::from_str::<Board>(&json).unwrap_err();
+        assert!(format!("{}", err).contains("too large"), "{}", err);
+    }
+
+    #[test]
+    fn board_is_owned() {
+        fn assert_owned<T: Send + Sync + 'static>() {}
+        assert_owned::<Board>();
+        assert_owned::<Land>();
+
+        let mut board = Board::build(2, 2, |_, _| LandKind::Plain.preset(10));
+        let glyph = format!("{}{}", 1, 2);
+        board.at_mut(1, 0).char = glyph.into();
+        let board = std::thread::spawn(move || board).join().unwrap();
+        assert_eq!(board.at(1, 0).char, "12");
+        assert_eq!(board.at(0, 0).char, "██");
+    }
 }
@@ -0,0 +1,405 @@
+//! This module provides a climate model to classify land into biomes by temperature and moisture
+//! in addition to altitude.
+//!
+//! Temperature is calculated from latitude of each row and altitude. Moisture is calculated from
+//! distance to water and prevailing wind. Wind brings moisture from the sea and loses it while it
+//! climbs up mountains, so land behind mountains becomes dry (rain shadow). Then land cells are
+//! classified into biomes such as desert, tundra, snow, swamp, jungle and savanna like Whittaker
+//! biome diagram.
+//!
+//! ```rust
+//! use world_map_gen::climate::{ClimateParams, Wind};
+//! use world_map_gen::gen::{GenConfig, RandomBoardGen};
+//!
+//! let config = GenConfig {
+//!     climate: Some(ClimateParams {
+//!         wind: Wind::East,
+//!         ..ClimateParams::default()
+//!     }),
+//!     ..GenConfig::default()
+//! };
+//! let board = RandomBoardGen::from_seed(42).with_config(config).gen_large(150, 150);
+//! assert_eq!(board.width(), 150);
+//! ```
+
+use crate::board::{Pos, Wrap};
+use crate::land::LandKind;
+use std::collections::VecDeque;
+
+/// Direction which prevailing wind blows from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Wind {
+    /// Wind blows from the left edge to the right edge of a board
+    West,
+    /// Wind blows from the right edge to the left edge of a board
+    East,
+    /// Wind blows from the top edge to the bottom edge of a board
+    North,
+    /// Wind blows from the bottom edge to the top edge of a board
+    South,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Wind::West
+    }
+}
+
+/// Parameters of climate model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClimateParams {
+    /// Temperature in Celsius at sea level on the equator.
+    pub equator_temperature: f64,
+    /// Temperature in Celsius at sea level on the poles.
+    pub pole_temperature: f64,
+    /// Latitudes in degrees of the top row and the bottom row of a board. Latitudes of other rows
+    /// are interpolated linearly.
+    pub latitudes: (f64, f64),
+    /// Decrease of temperature in Celsius per altitude above sea level.
+    pub lapse_rate: f64,
+    /// Direction of prevailing wind.
+    pub wind: Wind,
+    /// Multiplier of moisture. Larger value makes wetter world.
+    pub humidity: f64,
+}
+
+impl Default for ClimateParams {
+    fn default() -> Self {
+        ClimateParams {
+            equator_temperature: 30.0,
+            pole_temperature: -25.0,
+            latitudes: (45.0, -45.0),
+            lapse_rate: 0.4,
+            wind: Wind::West,
+            humidity: 1.0,
+        }
+    }
+}
+
+// Ratio of moisture which air loses per cell over flat land
+const DRYING: f64 = 0.01;
+// Ratio of moisture which air loses per altitude when it climbs up
+const OROGRAPHIC: f64 = 0.015;
+// Moisture of air coming from outside of board
+const INITIAL_AIR: f64 = 0.6;
+
+/// Temperature and moisture of each cell of a board.
+pub struct Climate {
+    temperature: Vec<Vec<f64>>,
+    moisture: Vec<Vec<f64>>,
+}
+
+impl Climate {
+    /// Calculates climate of cells from their altitudes. Cells whose altitudes are lower than or
+    /// equal to `sea_level` are water. When `sea_level` is `None`, there is no water. On wrapped
+    /// boards, distance to water and wind are continued across connected edges.
+    pub fn new(
+        params: &ClimateParams,
+        altitudes: &[Vec<u8>],
+        sea_level: Option<u8>,
+        wrap: Wrap,
+    ) -> Climate {
+        let is_water = |alt: u8| sea_level.map(|l| alt <= l).unwrap_or(false);
+        let base = sea_level.unwrap_or(0);
+        let height = altitudes.len();
+
+        let (top, bottom) = params.latitudes;
+        let temperature = altitudes
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                // On wasm32, usize has 32bits length so converting from usize to f64 is lossless.
+                #[allow(clippy::cast_lossless)]
+                let ratio = if height > 1 {
+                    y as f64 / (height - 1) as f64
+                } else {
+                    0.5
+                };
+                let latitude = top + (bottom - top) * ratio;
+                let temp = params.equator_temperature
+                    - (params.equator_temperature - params.pole_temperature) * latitude.abs()
+                        / 90.0;
+                row.iter()
+                    .map(|alt| {
+                        let above = f64::from(alt.saturating_sub(base));
+                        temp - params.lapse_rate * above
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let proximity = sea_proximity(altitudes, is_water, wrap);
+        let wind = wind_moisture(altitudes, is_water, params.wind, wrap);
+        let moisture = proximity
+            .iter()
+            .zip(wind.iter())
+            .map(|(prox, wind)| {
+                prox.iter()
+                    .zip(wind.iter())
+                    .map(|(p, w)| ((p + w) / 2.0 * params.humidity).clamp(0.0, 1.0))
+                    .collect()
+            })
+            .collect();
+
+        Climate {
+            temperature,
+            moisture,
+        }
+    }
+
+    /// Returns temperature in Celsius at the cell.
+    #[inline]
+    pub fn temperature(&self, x: usize, y: usize) -> f64 {
+        self.temperature[y][x]
+    }
+
+    /// Returns moisture in 0.0..=1.0 at the cell.
+    #[inline]
+    pub fn moisture(&self, x: usize, y: usize) -> f64 {
+        self.moisture[y][x]
+    }
+
+    /// Classifies the cell into a biome. `kind` is the land kind of the cell classified by its
+    /// altitude. Water and non-natural kinds such as towns are returned as they are.
+    pub fn classify(&self, kind: LandKind, x: usize, y: usize) -> LandKind {
+        use LandKind::*;
+
+        let natural = matches!(kind, Plain | Forest | Mountain | Highland);
+        if !natural {
+            return kind;
+        }
+
+        let temp = self.temperature(x, y);
+        let moist = self.moisture(x, y);
+        if temp < -10.0 {
+            return Snow;
+        }
+        if kind == Mountain || kind == Highland {
+            return kind;
+        }
+        if temp < 0.0 {
+            Tundra
+        } else if temp >= 20.0 {
+            if moist < 0.25 {
+                Desert
+            } else if moist < 0.5 {
+                Savanna
+            } else if moist >= 0.75 {
+                Jungle
+            } else {
+                kind
+            }
+        } else if moist < 0.15 {
+            Desert
+        } else if moist > 0.85 && kind == Plain {
+            Swamp
+        } else {
+            kind
+        }
+    }
+}
+
+// Proximity to water in 0.0..=1.0. It decreases exponentially by distance from water. Distance is
+// scaled by the size of the board.
+fn sea_proximity(
+    altitudes: &[Vec<u8>],
+    is_water: impl Fn(u8) -> bool,
+    wrap: Wrap,
+) -> Vec<Vec<f64>> {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let mut dist = vec![vec![usize::MAX; width]; height];
+
+    let mut queue = VecDeque::new();
+    for (y, row) in altitudes.iter().enumerate() {
+        for (x, alt) in row.iter().enumerate() {
+            if is_water(*alt) {
+                dist[y][x] = 0;
+                queue.push_back((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        let d = dist[y][x] + 1;
+        for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            if let Some(Pos { x: nx, y: ny }) = wrap.offset(&Pos { x, y }, dx, dy, width, height) {
+                if dist[ny][nx] > d {
+                    dist[ny][nx] = d;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+
+    // On wasm32, usize has 32bits length so converting from usize to f64 is lossless.
+    #[allow(clippy::cast_lossless)]
+    let scale = ((width + height) as f64 / 10.0).max(1.0);
+    dist.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|d| {
+                    if d == usize::MAX {
+                        0.0
+                    } else {
+                        (-(d as f64) / scale).exp()
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Moisture carried by prevailing wind in 0.0..=1.0. Air is filled with moisture over water and
+// loses it gradually over land. Air loses more moisture when climbing up slopes, which makes
+// windward slopes wet and leeward land dry. When the board wraps along the wind, air blows around
+// the board twice so that air coming across the edge has moisture of the other side.
+fn wind_moisture(
+    altitudes: &[Vec<u8>],
+    is_water: impl Fn(u8) -> bool,
+    wind: Wind,
+    wrap: Wrap,
+) -> Vec<Vec<f64>> {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let mut moisture = vec![vec![0.0; width]; height];
+
+    // Each line is a list of cells along the wind
+    let lines: Vec<Vec<(usize, usize)>> = match wind {
+        Wind::West => (0..height)
+            .map(|y| (0..width).map(|x| (x, y)).collect())
+            .collect(),
+        Wind::East => (0..height)
+            .map(|y| (0..width).rev().map(|x| (x, y)).collect())
+            .collect(),
+        Wind::North => (0..width)
+            .map(|x| (0..height).map(|y| (x, y)).collect())
+            .collect(),
+        Wind::South => (0..width)
+            .map(|x| (0..height).rev().map(|y| (x, y)).collect())
+            .collect(),
+    };
+
+    let around = match wind {
+        Wind::West | Wind::East => wrap.horizontal(),
+        Wind::North | Wind::South => wrap.vertical(),
+    };
+    for line in lines {
+        let mut air = INITIAL_AIR;
+        let mut prev = None;
+        let laps = if around { 2 } else { 1 };
+        for &(x, y) in line.iter().cycle().take(line.len() * laps) {
+            let alt = altitudes[y][x];
+            if is_water(alt) {
+                air = 1.0;
+                moisture[y][x] = 1.0;
+            } else {
+                let rise = prev
+                    .map(|p| f64::from(alt.saturating_sub(p)))
+                    .unwrap_or(0.0);
+                let rain = air * (DRYING + OROGRAPHIC * rise).min(1.0);
+                air -= rain;
+                // Rain falls on windward slopes
+                moisture[y][x] = (air + rain * 4.0).min(1.0);
+            }
+            prev = Some(alt);
+        }
+    }
+
+    moisture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_by_latitude_and_altitude() {
+        let alts = vec![vec![10, 10, 90]; 5];
+        let c = Climate::new(&ClimateParams::default(), &alts, Some(0), Wrap::None);
+        // Equator is at the middle row
+        assert!(c.temperature(0, 2) > c.temperature(0, 0));
+        assert!(c.temperature(0, 2) > c.temperature(0, 4));
+        assert!((c.temperature(0, 0) - c.temperature(0, 4)).abs() < 1e-9);
+        // Higher is colder
+        assert!(c.temperature(2, 2) < c.temperature(0, 2));
+    }
+
+    #[test]
+    fn rain_shadow_behind_mountain() {
+        let row = vec![0, 10, 10, 40, 70, 99, 70, 40, 10, 10, 10];
+        let alts = vec![row; 3];
+        let c = Climate::new(&ClimateParams::default(), &alts, Some(0), Wrap::None);
+        // Windward slope is wetter than leeward land at the same altitude
+        assert!(c.moisture(3, 1) > c.moisture(7, 1));
+        assert!(c.moisture(2, 1) > c.moisture(9, 1));
+
+        let params = ClimateParams {
+            wind: Wind::East,
+            ..ClimateParams::default()
+        };
+        let c = Climate::new(&params, &alts, Some(0), Wrap::None);
+        assert!(c.moisture(7, 1) > c.moisture(3, 1));
+    }
+
+    #[test]
+    fn climate_across_edges() {
+        // Sea is only at the right edge and wind blows from the west
+        let row = vec![10, 10, 10, 10, 10, 10, 10, 10, 10, 0];
+        let alts = vec![row; 3];
+        let c = Climate::new(&ClimateParams::default(), &alts, Some(0), Wrap::None);
+        let w = Climate::new(&ClimateParams::default(), &alts, Some(0), Wrap::Horizontal);
+        // Air coming across the edge is wet and the sea is adjacent across the edge
+        assert!(w.moisture(0, 1) > c.moisture(0, 1));
+        assert!(w.moisture(0, 1) > w.moisture(4, 1));
+    }
+
+    #[test]
+    fn classify_biomes() {
+        use LandKind::*;
+
+        let alts = vec![vec![0, 10, 99]];
+        let hot = ClimateParams {
+            latitudes: (0.0, 0.0),
+            lapse_rate: 0.0,
+            ..ClimateParams::default()
+        };
+        let wet = Climate::new(
+            &ClimateParams {
+                humidity: 10.0,
+                ..hot.clone()
+            },
+            &alts,
+            Some(0),
+            Wrap::None,
+        );
+        assert_eq!(wet.classify(Plain, 1, 0), Jungle);
+        assert_eq!(wet.classify(Sea, 0, 0), Sea);
+        assert_eq!(wet.classify(Town, 1, 0), Town);
+        assert_eq!(wet.classify(Mountain, 2, 0), Mountain);
+
+        let dry = Climate::new(
+            &ClimateParams {
+                humidity: 0.0,
+                ..hot
+            },
+            &alts,
+            Some(0),
+            Wrap::None,
+        );
+        assert_eq!(dry.classify(Plain, 1, 0), Desert);
+
+        let cold = Climate::new(
+            &ClimateParams {
+                latitudes: (90.0, 90.0),
+                ..ClimateParams::default()
+            },
+            &alts,
+            Some(0),
+            Wrap::None,
+        );
+        assert_eq!(cold.classify(Plain, 1, 0), Snow);
+        assert_eq!(cold.classify(Mountain, 2, 0), Snow);
+        assert_eq!(cold.classify(Sea, 0, 0), Sea);
+    }
+}
@@ -1,15 +1,13 @@
-use serde;
-use serde_json;
-use termcolor;
-use termcolor::ColorSpec;
-use termcolor2rgb;
+use serde::de::{self, Deserialize};
+use std::collections::HashMap;
+use termcolor::{Color, ColorSpec};
+use termcolor2rgb::ColorExt;
 
 pub struct SerializableColorSpec<'a>(pub &'a ColorSpec);
 
 impl<'a> serde::Serialize for SerializableColorSpec<'a> {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         use serde::ser::SerializeMap;
-        use termcolor2rgb::ColorExt;
 
         let mut map = serializer.serialize_map(Some(4))?;
 
@@ -30,10 +28,82 @@ impl<'a> serde::Serialize for SerializableColorSpec<'a> {
     }
 }
 
+// Returns RGB color to paint a cell with the color spec as an image. Foreground color is used
+// since a cell is rendered as filled characters. When no color is set, it falls back to black.
+pub fn cell_rgb(spec: &ColorSpec) -> (u8, u8, u8) {
+    spec.fg()
+        .or_else(|| spec.bg())
+        .map(|c| c.to_rgb())
+        .unwrap_or((0, 0, 0))
+}
+
+lazy_static! {
+    // Reverse lookup table from RGB to 256 colors. Colors in 16..=255 are preferred since system
+    // colors in 0..=15 have the same RGB values as some of them.
+    static ref ANSI256_BY_RGB: HashMap<(u8, u8, u8), u8> = {
+        let mut m = HashMap::new();
+        for i in (16..=255).chain(0..16) {
+            m.entry(Color::Ansi256(i).to_rgb()).or_insert(i);
+        }
+        m
+    };
+}
+
+// Parse "#rrggbb" color string. When the color is one of 256 colors, Color::Ansi256 is returned
+// to restore colors serialized by SerializableColorSpec. Otherwise Color::Rgb is returned.
+fn parse_color(s: &str) -> Option<Color> {
+    if s.len() != 7 || !s.starts_with('#') {
+        return None;
+    }
+    let hex = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+    let rgb = (hex(1)?, hex(3)?, hex(5)?);
+    Some(match ANSI256_BY_RGB.get(&rgb) {
+        Some(i) => Color::Ansi256(*i),
+        None => Color::Rgb(rgb.0, rgb.1, rgb.2),
+    })
+}
+
+// Deserialize a color spec from the format which SerializableColorSpec serializes into
+pub fn deserialize_color_spec<'de, D>(deserializer: D) -> Result<ColorSpec, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Repr {
+        fg: Option<String>,
+        bg: Option<String>,
+        #[serde(default)]
+        bold: bool,
+        #[serde(default)]
+        underline: bool,
+        #[serde(default)]
+        intense: bool,
+    }
+
+    let repr = Repr::deserialize(deserializer)?;
+    let color = |s: Option<String>| match s {
+        None => Ok(None),
+        Some(s) => match parse_color(&s) {
+            Some(c) => Ok(Some(c)),
+            None => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&s),
+                &"color string in #rrggbb format",
+            )),
+        },
+    };
+
+    let mut spec = ColorSpec::new();
+    spec.set_fg(color(repr.fg)?)
+        .set_bg(color(repr.bg)?)
+        .set_bold(repr.bold)
+        .set_underline(repr.underline)
+        .set_intense(repr.intense);
+    Ok(spec)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use termcolor::Color;
 
     #[test]
     fn serialize_spec_8bit_and_256bit() {
@@ -92,4 +162,42 @@ mod tests {
         .unwrap();
         assert_eq!(actual, expect);
     }
+
+    #[test]
+    fn deserialize_spec() {
+        #[derive(Deserialize)]
+        struct Wrap(#[serde(deserialize_with = "deserialize_color_spec")] ColorSpec);
+
+        for (fg, bg) in &[
+            (
+                Some(Color::Ansi256(156)),
+                Some(Color::Rgb(0x12, 0x34, 0x56)),
+            ),
+            (Some(Color::Ansi256(16)), None),
+            (None, Some(Color::Ansi256(255))),
+        ] {
+            let mut c = ColorSpec::new();
+            c.set_fg(*fg).set_bg(*bg).set_bold(true).set_underline(true);
+            let json = serde_json::to_string(&SerializableColorSpec(&c)).unwrap();
+            let Wrap(actual) = serde_json::from_str(&json).unwrap();
+            assert_eq!(actual, c, "{}", json);
+        }
+
+        // System colors are restored as the same colors in 16..=255
+        let Wrap(c) = serde_json::from_str(r##"{"fg": "#ff0000", "bg": null}"##).unwrap();
+        assert_eq!(c.fg(), Some(&Color::Ansi256(196)));
+        assert!(!c.bold());
+
+        for invalid in &[
+            r##"{"fg": "red"}"##,
+            r##"{"fg": "#12345g"}"##,
+            r##"{"fg": "#1234"}"##,
+        ] {
+            assert!(
+                serde_json::from_str::<Wrap>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
 }
@@ -0,0 +1,237 @@
+use rand;
+
+use self::rand::Rng;
+use crate::board::Wrap;
+
+#[inline]
+fn displace<R: Rng>(rng: &mut R, scale: f64) -> f64 {
+    if scale > 0.0 {
+        rng.gen_range(-scale, scale)
+    } else {
+        0.0
+    }
+}
+
+// Side of squares and width and height of the grid on which diamond-square algorithm works. The
+// grid is tiled with squares whose side is 2^n no smaller than the shorter side of the board so
+// that it is not much larger than the board even if the board is long and thin. Cells are at
+// corners of the squares, so the grid has one more cell on each axis unless it is periodic.
+fn grid_shape(width: usize, height: usize, periodic: bool) -> (usize, usize, usize) {
+    let extra = if periodic { 0 } else { 1 };
+    let mut side = 1;
+    while side + extra < width.min(height) {
+        side *= 2;
+    }
+    let len = |l: usize| {
+        let squares = (l.max(extra + 1) - extra + side - 1) / side;
+        squares * side + extra
+    };
+    (side, len(width), len(height))
+}
+
+// Generate a heightfield with diamond-square algorithm (midpoint displacement). The algorithm works
+// on a grid tiled with squares whose side is 2^n. Corners of the squares are initialized randomly
+// and each square is subdivided. The grid is cropped to the given width and height. Range of
+// random displacement is multiplied by `roughness` at each step. Returned values are not
+// normalized. When the board wraps, a periodic grid is generated instead and it is resampled to
+// the size on wrapped axes so that the opposite edges are continuous.
+pub fn diamond_square<R: Rng>(
+    rng: &mut R,
+    width: usize,
+    height: usize,
+    roughness: f64,
+    wrap: Wrap,
+) -> Vec<Vec<f64>> {
+    if wrap != Wrap::None {
+        return periodic(rng, width, height, roughness, wrap);
+    }
+
+    let (n, w, h) = grid_shape(width, height, false);
+    let mut grid = vec![vec![0.0; w]; h];
+
+    for y in (0..h).step_by(n) {
+        for x in (0..w).step_by(n) {
+            grid[y][x] = rng.gen_range(-1.0, 1.0);
+        }
+    }
+
+    let mut step = n;
+    let mut scale = 1.0;
+    while step > 1 {
+        let half = step / 2;
+
+        // Diamond step: center of each square is average of its 4 corners
+        for y in (half..h).step_by(step) {
+            for x in (half..w).step_by(step) {
+                let avg = (grid[y - half][x - half]
+                    + grid[y - half][x + half]
+                    + grid[y + half][x - half]
+                    + grid[y + half][x + half])
+                    / 4.0;
+                grid[y][x] = avg + displace(rng, scale);
+            }
+        }
+
+        // Square step: midpoint of each edge is average of its (at most) 4 neighbors in diamond
+        for y in (0..h).step_by(half) {
+            let start = if (y / half) % 2 == 0 { half } else { 0 };
+            for x in (start..w).step_by(step) {
+                let mut sum = 0.0;
+                let mut count = 0.0;
+                if y >= half {
+                    sum += grid[y - half][x];
+                    count += 1.0;
+                }
+                if y + half < h {
+                    sum += grid[y + half][x];
+                    count += 1.0;
+                }
+                if x >= half {
+                    sum += grid[y][x - half];
+                    count += 1.0;
+                }
+                if x + half < w {
+                    sum += grid[y][x + half];
+                    count += 1.0;
+                }
+                grid[y][x] = sum / count + displace(rng, scale);
+            }
+        }
+
+        step = half;
+        scale *= roughness;
+    }
+
+    grid.truncate(height);
+    for row in grid.iter_mut() {
+        row.truncate(width);
+    }
+    grid
+}
+
+// Diamond-square algorithm on a grid whose opposite edges are connected. Neighbors across edges are
+// used for averages so the grid tiles seamlessly.
+fn periodic<R: Rng>(
+    rng: &mut R,
+    width: usize,
+    height: usize,
+    roughness: f64,
+    wrap: Wrap,
+) -> Vec<Vec<f64>> {
+    let (n, w, h) = grid_shape(width, height, true);
+    let mut grid = vec![vec![0.0; w]; h];
+    for y in (0..h).step_by(n) {
+        for x in (0..w).step_by(n) {
+            grid[y][x] = rng.gen_range(-1.0, 1.0);
+        }
+    }
+
+    let mut step = n;
+    let mut scale = 1.0;
+    while step > 1 {
+        let half = step / 2;
+
+        for y in (half..h).step_by(step) {
+            for x in (half..w).step_by(step) {
+                let (l, r) = (x - half, (x + half) % w);
+                let (t, b) = (y - half, (y + half) % h);
+                let avg = (grid[t][l] + grid[t][r] + grid[b][l] + grid[b][r]) / 4.0;
+                grid[y][x] = avg + displace(rng, scale);
+            }
+        }
+
+        for y in (0..h).step_by(half) {
+            let start = if (y / half) % 2 == 0 { half } else { 0 };
+            for x in (start..w).step_by(step) {
+                let sum = grid[(y + h - half) % h][x]
+                    + grid[(y + half) % h][x]
+                    + grid[y][(x + w - half) % w]
+                    + grid[y][(x + half) % w];
+                grid[y][x] = sum / 4.0 + displace(rng, scale);
+            }
+        }
+
+        step = half;
+        scale *= roughness;
+    }
+
+    // Resample the grid to the length on wrapped axis by linear interpolation. Otherwise crop it.
+    let sample = |i: usize, len: usize, n: usize, wrapped: bool| {
+        if wrapped {
+            let p = i as f64 * n as f64 / len as f64;
+            let j = p.floor() as usize;
+            (j % n, (j + 1) % n, p - p.floor())
+        } else {
+            (i, i, 0.0)
+        }
+    };
+    (0..height)
+        .map(|y| {
+            let (y0, y1, v) = sample(y, height, h, wrap.vertical());
+            (0..width)
+                .map(|x| {
+                    let (x0, x1, u) = sample(x, width, w, wrap.horizontal());
+                    let top = grid[y0][x0] * (1.0 - u) + grid[y0][x1] * u;
+                    let bottom = grid[y1][x0] * (1.0 - u) + grid[y1][x1] * u;
+                    top * (1.0 - v) + bottom * v
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_size() {
+        let mut rng = rand::thread_rng();
+        for &(w, h) in &[(1, 1), (2, 3), (17, 17), (40, 30), (100, 3)] {
+            for wrap in &[Wrap::None, Wrap::Horizontal, Wrap::Both] {
+                let g = diamond_square(&mut rng, w, h, 0.5, *wrap);
+                assert_eq!(g.len(), h);
+                assert!(g.iter().all(|r| r.len() == w), "{}x{} {:?}", w, h, wrap);
+            }
+        }
+        assert!(diamond_square(&mut rng, 0, 0, 0.5, Wrap::None).is_empty());
+        assert!(diamond_square(&mut rng, 0, 0, 0.5, Wrap::Both).is_empty());
+    }
+
+    #[test]
+    fn grid_of_long_board() {
+        // Squares are as large as the shorter side so the grid is not much larger than the board
+        assert_eq!(grid_shape(4096, 8, false), (8, 4097, 9));
+        assert_eq!(grid_shape(8, 4096, true), (8, 8, 4096));
+        assert_eq!(grid_shape(40, 30, false), (32, 65, 33));
+        assert_eq!(grid_shape(40, 30, true), (32, 64, 32));
+        assert_eq!(grid_shape(0, 0, false), (1, 2, 2));
+        assert_eq!(grid_shape(0, 0, true), (1, 1, 1));
+
+        let mut rng = rand::thread_rng();
+        let g = diamond_square(&mut rng, 4096, 8, 0.5, Wrap::Horizontal);
+        assert_eq!(g.len(), 8);
+        assert!(g.iter().all(|r| r.len() == 4096));
+    }
+
+    #[test]
+    fn smooth_without_roughness() {
+        // Without displacement except for the first step, neighbors differ only slightly
+        let mut rng = rand::thread_rng();
+        let g = diamond_square(&mut rng, 33, 33, 0.0, Wrap::None);
+        for (y, row) in g.iter().enumerate() {
+            for (x, w) in row.windows(2).enumerate() {
+                assert!((w[0] - w[1]).abs() < 0.5, "({}, {})", x, y);
+            }
+        }
+
+        // The opposite edges are continuous on wrapped grid
+        let g = diamond_square(&mut rng, 40, 20, 0.0, Wrap::Both);
+        for row in g.iter() {
+            assert!((row[0] - row[39]).abs() < 0.5);
+        }
+        for (t, b) in g[0].iter().zip(g[19].iter()) {
+            assert!((t - b).abs() < 0.5);
+        }
+    }
+}
@@ -1,19 +1,26 @@
-//! Helper to draw a generated map to terminal screen or as JSON.
+//! Helper to draw a generated map to terminal screen, as JSON, as PNG image, as SVG image, as map
+//! of Tiled map editor or as 3D mesh.
 //!
 //! Terminal must support 256colors. And large map may require much time and CPU usage to render map.
 
+use png;
 use serde_json;
 use termcolor;
 
 use self::termcolor::{BufferedStandardStream, ColorChoice, ColorSpec, WriteColor};
-use crate::board::Board;
-use crate::error::Result;
+use crate::board::{Board, CompactBoard, Layout};
+use crate::color::cell_rgb;
+use crate::error::{Error, Result};
+use crate::land::{Land, LandKind};
+use crate::mesh::Mesh;
+use crate::tiled;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::Write;
 
 /// Render the given board to terminal screen. When the `show_altitude` flag is set to true, it
 /// renders the altitude value for each cell instead of each cell's characters. This flag is
-/// usually enabled for debugging purpose.
+/// usually enabled for debugging purpose. On hex layout, odd rows are staggered by half a cell.
 /// When writing to terminal fails, it returns an error.
 ///
 /// ```rust
@@ -25,11 +32,20 @@ use std::io::Write;
 ///
 /// draw_term(&board, true).unwrap();
 /// ```
-pub fn draw_term(board: &Board<'_>, show_altitude: bool) -> Result<()> {
+pub fn draw_term(board: &Board, show_altitude: bool) -> Result<()> {
     let stdout = &mut BufferedStandardStream::stdout(ColorChoice::Always);
     let mut prev = ColorSpec::default();
     let mut legends = HashMap::new();
-    for row in board.rows() {
+    let hex = board.layout() == Layout::Hex;
+    for (y, row) in board.rows().enumerate() {
+        if hex && y % 2 == 1 {
+            // Stagger odd rows by half a cell on hex layout
+            if prev != ColorSpec::default() {
+                stdout.reset()?;
+                prev = ColorSpec::default();
+            }
+            write!(stdout, " ")?;
+        }
         for cell in row.iter() {
             if prev != cell.color {
                 stdout.set_color(&cell.color)?;
@@ -46,8 +62,9 @@ pub fn draw_term(board: &Board<'_>, show_altitude: bool) -> Result<()> {
     }
 
     // Write legends
-    let term_width = board.width() * 2; // since a cell consists of half-width character * 2
-    let mut width = usize::max_value();
+    // Since a cell consists of half-width character * 2. Odd rows are staggered on hex layout
+    let term_width = board.width() * 2 + usize::from(hex);
+    let mut width = usize::MAX;
     for (legend, cell) in legends.iter() {
         let legend_len = cell.char.chars().count() + 3 + legend.len();
         if width.saturating_add(legend_len) > term_width {
@@ -67,8 +84,10 @@ pub fn draw_term(board: &Board<'_>, show_altitude: bool) -> Result<()> {
     Ok(())
 }
 
-/// Render the given board as JSON to given writer. When deserializing the board as JSON or writing
-/// the result to the writer failed, it returns an error.
+/// Render the given board as JSON to given writer. Each cell has its land kind, character, color,
+/// altitude and legend. The JSON has `layout` of cells (`"Square"` or `"Hex"`) so that clients
+/// can render cells in the layout. When deserializing the board as JSON or writing the result to
+/// the writer failed, it returns an error.
 ///
 /// ```rust
 /// use world_map_gen::gen::RandomBoardGen;
@@ -85,15 +104,306 @@ pub fn draw_term(board: &Board<'_>, show_altitude: bool) -> Result<()> {
 ///
 /// println!("JSON: {}", std::str::from_utf8(&buffer).unwrap());
 /// ```
-pub fn draw_json<W: Write>(writer: &mut W, board: &Board<'_>) -> Result<()> {
+pub fn draw_json<W: Write>(writer: &mut W, board: &Board) -> Result<()> {
     serde_json::to_writer(writer, &board)?;
     Ok(())
 }
 
+/// Render the given board as compact JSON to given writer. Characters and colors are listed once
+/// per land kind in palette and cells are flat arrays of land kinds and altitudes. Please see
+/// `board::CompactBoard` for the details. When deserializing the board as JSON or writing the
+/// result to the writer failed, it returns an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::draw_compact_json;
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// let mut buffer = Vec::<u8>::new();
+/// draw_compact_json(&mut buffer, &board).unwrap();
+/// ```
+pub fn draw_compact_json<W: Write>(writer: &mut W, board: &Board) -> Result<()> {
+    serde_json::to_writer(writer, &CompactBoard(board))?;
+    Ok(())
+}
+
+/// Render the given board as PNG image to given writer. Each cell is painted as a `scale * scale`
+/// pixels square with the color of the cell. `scale` less than 1 is treated as 1. When the image is
+/// too large for PNG, or encoding the image or writing the result to the writer failed, it returns
+/// an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::draw_png;
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// // Writer to write the encoded PNG image. Each cell is drawn as 4x4 pixels
+/// let mut buffer = Vec::<u8>::new();
+///
+/// draw_png(&mut buffer, &board, 4).unwrap();
+/// ```
+pub fn draw_png<W: Write>(writer: W, board: &Board, scale: usize) -> Result<()> {
+    let scale = scale.max(1);
+    let too_large = || Error::CannotEncodePng(png::EncodingError::LimitsExceeded);
+    let width = board.width().checked_mul(scale).ok_or_else(too_large)?;
+    let height = board.height().checked_mul(scale).ok_or_else(too_large)?;
+    let (png_width, png_height) = png_size(width, height)?;
+    let len = width
+        .checked_mul(height)
+        .and_then(|len| len.checked_mul(3))
+        .ok_or_else(too_large)?;
+
+    let mut data = Vec::with_capacity(len);
+    for row in board.rows() {
+        let mut line = Vec::with_capacity(width * 3);
+        for cell in row.iter() {
+            let (r, g, b) = cell_rgb(&cell.color);
+            for _ in 0..scale {
+                line.extend_from_slice(&[r, g, b]);
+            }
+        }
+        for _ in 0..scale {
+            data.extend_from_slice(&line);
+        }
+    }
+
+    encode_rgb_png(writer, png_width, png_height, &data)
+}
+
+// Width and height of PNG image are 32-bit integers
+fn png_size(width: usize, height: usize) -> Result<(u32, u32)> {
+    match (u32::try_from(width), u32::try_from(height)) {
+        (Ok(w), Ok(h)) => Ok((w, h)),
+        _ => Err(Error::CannotEncodePng(png::EncodingError::LimitsExceeded)),
+    }
+}
+
+fn encode_rgb_png<W: Write>(writer: W, width: u32, height: u32, data: &[u8]) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+// Size of one cell in SVG image in pixels
+const SVG_CELL_SIZE: usize = 10;
+// Height of one line of legends in SVG image in pixels
+const SVG_LEGEND_HEIGHT: usize = 20;
+
+/// Render the given board as SVG image to given writer. Each cell is drawn as a square with the
+/// color of the cell. Its land kind and altitude are embedded as `data-kind` and `data-altitude`
+/// attributes so that they can be inspected. Legends for land kinds in the board are drawn below
+/// the map. When writing the result to the writer failed, it returns an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::draw_svg;
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// // Writer to write the SVG image
+/// let mut buffer = Vec::<u8>::new();
+///
+/// draw_svg(&mut buffer, &board).unwrap();
+///
+/// println!("SVG: {}", std::str::from_utf8(&buffer).unwrap());
+/// ```
+pub fn draw_svg<W: Write>(writer: &mut W, board: &Board) -> Result<()> {
+    let width = board.width() * SVG_CELL_SIZE;
+
+    // Lay out legends in order of appearance so that output is stable
+    let mut legends = Vec::new();
+    for cell in board.iter() {
+        if legends.iter().all(|c: &&Land| c.kind != cell.kind) {
+            legends.push(cell);
+        }
+    }
+    let mut lines = vec![Vec::new()];
+    let mut line_width = 0;
+    for cell in legends {
+        // Square, space, and text which is roughly estimated as 7px per character
+        let item_width = SVG_CELL_SIZE + 4 + cell.kind.legend().len() * 7 + 12;
+        if line_width > 0 && line_width + item_width > width {
+            lines.push(Vec::new());
+            line_width = 0;
+        }
+        lines.last_mut().unwrap().push((line_width, cell));
+        line_width += item_width;
+    }
+
+    let map_height = board.height() * SVG_CELL_SIZE;
+    let height = map_height + lines.len() * SVG_LEGEND_HEIGHT;
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height,
+    )?;
+
+    writeln!(writer, r#"<g class="cells" shape-rendering="crispEdges">"#)?;
+    for (y, row) in board.rows().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let (r, g, b) = cell_rgb(&cell.color);
+            writeln!(
+                writer,
+                r##"<rect x="{}" y="{}" width="{s}" height="{s}" fill="#{:02x}{:02x}{:02x}" data-kind="{:?}" data-altitude="{}"/>"##,
+                x * SVG_CELL_SIZE,
+                y * SVG_CELL_SIZE,
+                r,
+                g,
+                b,
+                cell.kind,
+                cell.altitude,
+                s = SVG_CELL_SIZE,
+            )?;
+        }
+    }
+    writeln!(writer, "</g>")?;
+
+    writeln!(
+        writer,
+        r#"<g class="legends" font-family="sans-serif" font-size="12">"#
+    )?;
+    for (i, line) in lines.iter().enumerate() {
+        let top = map_height + i * SVG_LEGEND_HEIGHT + (SVG_LEGEND_HEIGHT - SVG_CELL_SIZE) / 2;
+        for (left, cell) in line {
+            let (r, g, b) = cell_rgb(&cell.color);
+            writeln!(
+                writer,
+                r##"<rect x="{}" y="{}" width="{s}" height="{s}" fill="#{:02x}{:02x}{:02x}"/><text x="{}" y="{}">{}</text>"##,
+                left,
+                top,
+                r,
+                g,
+                b,
+                left + SVG_CELL_SIZE + 4,
+                top + SVG_CELL_SIZE,
+                cell.kind.legend(),
+                s = SVG_CELL_SIZE,
+            )?;
+        }
+    }
+    writeln!(writer, "</g>")?;
+
+    writeln!(writer, "</svg>")?;
+    Ok(())
+}
+
+/// Render the given board as a map of [Tiled map editor](https://www.mapeditor.org/) in its JSON
+/// format to given writer. The map refers to a tileset image at `tileset_image` path which is
+/// relative to the map file. The tileset image can be generated with `draw_tiled_tileset()`.
+/// Each tile in the tileset represents a pair of land kind and altitude, and has `kind` and
+/// `altitude` custom properties. Towns and paths are put on `features` layer separated from
+/// `terrain` layer so that they can be edited independently. Since land under towns and paths is
+/// unknown, plains are put under them on `terrain` layer. Each tile is `tile_size * tile_size`
+/// pixels. Boards in `Layout::Hex` are rendered as hexagonal maps whose odd rows are staggered.
+/// When serializing the map or writing the result to the writer failed, it returns an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::{draw_tiled, draw_tiled_tileset};
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// // Writers to write the map and the tileset image
+/// let mut map = Vec::<u8>::new();
+/// let mut tileset = Vec::<u8>::new();
+///
+/// draw_tiled(&mut map, &board, "tileset.png", 16).unwrap();
+/// draw_tiled_tileset(&mut tileset, 16).unwrap();
+/// ```
+pub fn draw_tiled<W: Write>(
+    writer: &mut W,
+    board: &Board,
+    tileset_image: &str,
+    tile_size: usize,
+) -> Result<()> {
+    let map = tiled::map(board, tileset_image, tile_size.max(1));
+    serde_json::to_writer(writer, &map)?;
+    Ok(())
+}
+
+/// Render the tileset image for maps rendered by `draw_tiled()` as PNG image to given writer.
+/// Each tile is painted as a `tile_size * tile_size` pixels square with the color of its land
+/// kind. When encoding the image or writing the result to the writer failed, it returns an error.
+pub fn draw_tiled_tileset<W: Write>(writer: W, tile_size: usize) -> Result<()> {
+    let tile_size = tile_size.max(1);
+    let (width, height) = tiled::tileset_size(tile_size);
+    let (png_width, png_height) = png_size(width, height)?;
+    let colors = LandKind::ALL
+        .iter()
+        .map(|k| cell_rgb(&k.preset_ref().color))
+        .collect::<Vec<_>>();
+
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let (kind, _) = tiled::tileset_tile(x, y, tile_size);
+            let (r, g, b) = colors[kind as usize];
+            data.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    encode_rgb_png(writer, png_width, png_height, &data)
+}
+
+/// Render altitudes of the given board as 3D triangle mesh in Wavefront OBJ format to given
+/// writer. Each cell is a vertex colored with the color of the cell and each 2x2 cells make two
+/// triangles. X axis is width, Y axis is altitude and Z axis is height of the board. One cell is
+/// one unit and one altitude is 0.1 unit multiplied by `exaggeration`. Vertex colors are written
+/// after coordinates of vertices, which is supported by many tools. When writing the result to
+/// the writer failed, it returns an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::draw_obj;
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// // Writer to write the mesh with doubled altitudes
+/// let mut buffer = Vec::<u8>::new();
+///
+/// draw_obj(&mut buffer, &board, 2.0).unwrap();
+/// ```
+pub fn draw_obj<W: Write>(writer: &mut W, board: &Board, exaggeration: f32) -> Result<()> {
+    Mesh::new(board, exaggeration).write_obj(writer)
+}
+
+/// Render altitudes of the given board as 3D triangle mesh in binary glTF 2.0 format to given
+/// writer. The mesh is the same as `draw_obj()`. When the board has less than 2x2 cells, it has
+/// no triangle and returns an error. When serializing the mesh or writing the result to the writer
+/// failed, it returns an error.
+///
+/// ```rust
+/// use world_map_gen::gen::RandomBoardGen;
+/// use world_map_gen::draw::draw_glb;
+///
+/// let mut gen = RandomBoardGen::default();
+/// let board = gen.gen_auto(3, 4);
+///
+/// // Writer to write the mesh with doubled altitudes
+/// let mut buffer = Vec::<u8>::new();
+///
+/// draw_glb(&mut buffer, &board, 2.0).unwrap();
+/// ```
+pub fn draw_glb<W: Write>(writer: &mut W, board: &Board, exaggeration: f32) -> Result<()> {
+    Mesh::new(board, exaggeration).write_glb(writer)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::board::Board;
+    use crate::board::{Board, Layout};
     use crate::land::LandKind;
 
     #[test]
@@ -106,6 +416,7 @@ mod tests {
             r##"{
                 "width": 1,
                 "height": 1,
+                "layout": "Square",
                 "cells": [
                     [
                         {
@@ -134,4 +445,181 @@ mod tests {
 
         assert_eq!(expect, actual);
     }
+
+    #[test]
+    fn draw_board_as_png() {
+        let b = Board::build(2, 1, |x, _| {
+            if x == 0 {
+                LandKind::Forest.preset(50)
+            } else {
+                LandKind::Sea.preset(10)
+            }
+        });
+
+        for scale in 1..=3 {
+            let mut buf = Vec::<u8>::new();
+            draw_png(&mut buf, &b, scale).unwrap();
+
+            let decoder = png::Decoder::new(buf.as_slice());
+            let mut reader = decoder.read_info().unwrap();
+            let mut img = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut img).unwrap();
+            assert_eq!(info.width as usize, 2 * scale);
+            assert_eq!(info.height as usize, scale);
+            assert_eq!(info.color_type, png::ColorType::Rgb);
+
+            let forest = cell_rgb(&LandKind::Forest.preset(0).color);
+            let sea = cell_rgb(&LandKind::Sea.preset(0).color);
+            for y in 0..scale {
+                for x in 0..2 * scale {
+                    let i = (y * 2 * scale + x) * 3;
+                    let expected = if x < scale { forest } else { sea };
+                    assert_eq!((img[i], img[i + 1], img[i + 2]), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draw_too_large_png() {
+        let b = Board::build(2, 1, |_, _| LandKind::Sea.preset(10));
+        for &scale in &[usize::MAX, u32::MAX as usize / 2 + 1] {
+            let mut buf = Vec::<u8>::new();
+            match draw_png(&mut buf, &b, scale) {
+                Err(Error::CannotEncodePng(png::EncodingError::LimitsExceeded)) => {}
+                r => panic!("Unexpected result for scale {}: {:?}", scale, r),
+            }
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn draw_board_as_svg() {
+        let b = Board::build(3, 2, |x, y| match (x, y) {
+            (0, 0) => LandKind::Town.preset(30),
+            (_, 0) => LandKind::Forest.preset(50),
+            _ => LandKind::Sea.preset(10),
+        });
+        let mut buf = Vec::<u8>::new();
+        draw_svg(&mut buf, &b).unwrap();
+        let svg = String::from_utf8(buf).unwrap();
+
+        assert!(svg.starts_with("<svg "), "{}", svg);
+        assert!(svg.trim_end().ends_with("</svg>"), "{}", svg);
+        assert_eq!(svg.matches("data-altitude=").count(), 6, "{}", svg);
+        assert!(svg.contains(
+            r##"<rect x="10" y="0" width="10" height="10" fill="#005f00" data-kind="Forest" data-altitude="50"/>"##
+        ), "{}", svg);
+        assert!(
+            svg.contains(r#"data-kind="Sea" data-altitude="10"/>"#),
+            "{}",
+            svg
+        );
+
+        // Legends are drawn once per kind in order of appearance
+        let legends = ["Town", "Forest", "Sea"]
+            .iter()
+            .map(|l| {
+                assert_eq!(svg.matches(&format!(">{}</text>", l)).count(), 1, "{}", svg);
+                svg.find(&format!(">{}</text>", l)).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            legends[0] < legends[1] && legends[1] < legends[2],
+            "{}",
+            svg
+        );
+    }
+
+    #[test]
+    fn draw_board_as_tiled_map() {
+        let b = Board::build(2, 2, |x, y| match (x, y) {
+            (0, 0) => LandKind::Town.preset(30),
+            (1, 0) => LandKind::Forest.preset(50),
+            _ => LandKind::Sea.preset(10),
+        });
+        let mut buf = Vec::<u8>::new();
+        draw_tiled(&mut buf, &b, "lands.png", 8).unwrap();
+        let map: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(map["width"], 2);
+        assert_eq!(map["tilewidth"], 8);
+        let tileset = &map["tilesets"][0];
+        assert_eq!(tileset["image"], "lands.png");
+        assert_eq!(tileset["columns"], 100);
+
+        let gid = |k: LandKind, alt: u32| k as u32 * 100 + alt + 1;
+        let layers = map["layers"].as_array().unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0]["name"], "terrain");
+        assert_eq!(
+            layers[0]["data"],
+            serde_json::json!([
+                gid(LandKind::Plain, 30),
+                gid(LandKind::Forest, 50),
+                gid(LandKind::Sea, 10),
+                gid(LandKind::Sea, 10),
+            ])
+        );
+        assert_eq!(layers[1]["name"], "features");
+        assert_eq!(
+            layers[1]["data"],
+            serde_json::json!([gid(LandKind::Town, 30), 0, 0, 0])
+        );
+
+        // Custom properties of used tiles
+        let tiles = tileset["tiles"].as_array().unwrap();
+        assert_eq!(tiles.len(), 4);
+        let forest = tiles
+            .iter()
+            .find(|t| t["id"] == gid(LandKind::Forest, 50) - 1)
+            .unwrap();
+        assert_eq!(forest["type"], "Forest");
+        let props = forest["properties"].as_array().unwrap();
+        assert!(props
+            .iter()
+            .any(|p| p["name"] == "altitude" && p["value"] == 50));
+        assert!(props
+            .iter()
+            .any(|p| p["name"] == "kind" && p["value"] == "Forest"));
+    }
+
+    #[test]
+    fn draw_hex_board_as_tiled_map() {
+        let mut b = Board::build(2, 2, |_, _| LandKind::Plain.preset(30));
+        let mut buf = Vec::<u8>::new();
+        draw_tiled(&mut buf, &b, "lands.png", 8).unwrap();
+        let map: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(map["orientation"], "orthogonal");
+        assert!(map.get("staggeraxis").is_none());
+        assert!(map.get("hexsidelength").is_none());
+
+        b.set_layout(Layout::Hex);
+        let mut buf = Vec::<u8>::new();
+        draw_tiled(&mut buf, &b, "lands.png", 8).unwrap();
+        let map: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(map["orientation"], "hexagonal");
+        assert_eq!(map["staggeraxis"], "y");
+        assert_eq!(map["staggerindex"], "odd");
+        assert_eq!(map["hexsidelength"], 4);
+    }
+
+    #[test]
+    fn draw_tiled_tileset_image() {
+        let mut buf = Vec::<u8>::new();
+        draw_tiled_tileset(&mut buf, 2).unwrap();
+
+        let decoder = png::Decoder::new(buf.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut img = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut img).unwrap();
+        assert_eq!(info.width, 200);
+        assert_eq!(info.height as usize, LandKind::ALL.len() * 2);
+
+        for (row, kind) in LandKind::ALL.iter().enumerate() {
+            let i = ((row * 2 + 1) * 200 + 123) * 3;
+            let expected = cell_rgb(&kind.preset_ref().color);
+            assert_eq!((img[i], img[i + 1], img[i + 2]), expected, "{:?}", kind);
+        }
+    }
 } // mod tests
@@ -0,0 +1,293 @@
+use rand;
+
+use self::rand::Rng;
+use crate::board::Wrap;
+
+// Constants of droplet simulation. Heights are in altitude unit (0..=99) and distances are in
+// number of cells.
+const INERTIA: f64 = 0.05;
+const MIN_CAPACITY: f64 = 0.01;
+const CAPACITY: f64 = 4.0;
+const DEPOSIT_RATE: f64 = 0.3;
+const EVAPORATION: f64 = 0.02;
+const GRAVITY: f64 = 4.0;
+const LIFETIME: usize = 40;
+
+// Thermal erosion moves this ratio of exceeding height difference per iteration
+const THERMAL_RATE: f64 = 0.25;
+
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<f64>,
+    wrap: Wrap,
+}
+
+impl Grid {
+    // Index of the cell. Coordinates beyond the size are wrapped around, which only happens on
+    // wrapped axes.
+    #[inline]
+    fn index(&self, x: usize, y: usize) -> usize {
+        y % self.height * self.width + x % self.width
+    }
+
+    #[inline]
+    fn at(&self, x: usize, y: usize) -> f64 {
+        self.cells[self.index(x, y)]
+    }
+
+    // Returns height and gradient at the position by bilinear interpolation
+    fn height_and_gradient(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let (ix, iy) = (x as usize, y as usize);
+        let (u, v) = (x - ix as f64, y - iy as f64);
+        let nw = self.at(ix, iy);
+        let ne = self.at(ix + 1, iy);
+        let sw = self.at(ix, iy + 1);
+        let se = self.at(ix + 1, iy + 1);
+        let gx = (ne - nw) * (1.0 - v) + (se - sw) * v;
+        let gy = (sw - nw) * (1.0 - u) + (se - ne) * u;
+        let h = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+        (h, gx, gy)
+    }
+
+    // Adds the amount to 4 cells around the position with bilinear weights
+    fn add(&mut self, x: f64, y: f64, amount: f64) {
+        let (ix, iy) = (x as usize, y as usize);
+        let (u, v) = (x - ix as f64, y - iy as f64);
+        let (nw, ne) = (self.index(ix, iy), self.index(ix + 1, iy));
+        let (sw, se) = (self.index(ix, iy + 1), self.index(ix + 1, iy + 1));
+        self.cells[nw] += amount * (1.0 - u) * (1.0 - v);
+        self.cells[ne] += amount * u * (1.0 - v);
+        self.cells[sw] += amount * (1.0 - u) * v;
+        self.cells[se] += amount * u * v;
+    }
+
+    // Returns the position in the grid. Positions beyond wrapped edges are wrapped around. When
+    // the position is out of the grid, returns `None`.
+    #[inline]
+    fn locate(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let locate_on = |v: f64, len: usize, wrap: bool| {
+            let len = len as f64;
+            if wrap {
+                Some(v.rem_euclid(len))
+            } else if v >= 0.0 && v < len - 1.0 {
+                Some(v)
+            } else {
+                None
+            }
+        };
+        Some((
+            locate_on(x, self.width, self.wrap.horizontal())?,
+            locate_on(y, self.height, self.wrap.vertical())?,
+        ))
+    }
+}
+
+// Simulate droplets of water. Each droplet runs down the steepest slope, erodes soil where it
+// speeds up and deposits sediment where it slows down or reaches flat land.
+fn hydraulic<R: Rng>(rng: &mut R, grid: &mut Grid, droplets: usize, strength: f64) {
+    if grid.width < 2 || grid.height < 2 {
+        return;
+    }
+    let (max_x, max_y) = ((grid.width - 1) as f64, (grid.height - 1) as f64);
+
+    for _ in 0..droplets {
+        let mut x = rng.gen_range(0.0, max_x);
+        let mut y = rng.gen_range(0.0, max_y);
+        let (mut dx, mut dy) = (0.0, 0.0);
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..LIFETIME {
+            let (h, gx, gy) = grid.height_and_gradient(x, y);
+            dx = dx * INERTIA - gx * (1.0 - INERTIA);
+            dy = dy * INERTIA - gy * (1.0 - INERTIA);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len < 1e-9 {
+                break;
+            }
+            dx /= len;
+            dy /= len;
+
+            let (px, py) = (x, y);
+            match grid.locate(x + dx, y + dy) {
+                Some((nx, ny)) => {
+                    x = nx;
+                    y = ny;
+                }
+                None => break,
+            }
+
+            let (new_h, ..) = grid.height_and_gradient(x, y);
+            let delta = new_h - h;
+            let capacity = (-delta * speed * water * CAPACITY * strength).max(MIN_CAPACITY);
+
+            if sediment > capacity || delta > 0.0 {
+                // Fill the pit when going up, otherwise drop surplus sediment
+                let amount = if delta > 0.0 {
+                    delta.min(sediment)
+                } else {
+                    (sediment - capacity) * DEPOSIT_RATE
+                };
+                sediment -= amount;
+                grid.add(px, py, amount);
+            } else {
+                // Never erode more than height difference not to dig a hole
+                let amount = ((capacity - sediment) * strength).min(-delta);
+                sediment += amount;
+                grid.add(px, py, -amount);
+            }
+
+            speed = (speed * speed - delta * GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - EVAPORATION;
+        }
+    }
+}
+
+// Collapse steep slopes. When height difference between adjacent cells exceeds the talus, a part
+// of the exceeding soil slides down to the lower cell.
+fn thermal(grid: &mut Grid, iterations: usize, talus: f64) {
+    let (w, h) = (grid.width, grid.height);
+    let mut deltas = vec![0.0; w * h];
+    for _ in 0..iterations {
+        for d in deltas.iter_mut() {
+            *d = 0.0;
+        }
+        for y in 0..h {
+            for x in 0..w {
+                let i = y * w + x;
+                for &(nx, ny) in &[(x + 1, y), (x, y + 1)] {
+                    let wrapped = (nx == w && grid.wrap.horizontal() && w > 1)
+                        || (ny == h && grid.wrap.vertical() && h > 1);
+                    if (nx >= w || ny >= h) && !wrapped {
+                        continue;
+                    }
+                    let j = grid.index(nx, ny);
+                    let diff = grid.cells[i] - grid.cells[j];
+                    if diff.abs() <= talus {
+                        continue;
+                    }
+                    let amount = (diff.abs() - talus) * THERMAL_RATE / 2.0;
+                    let (from, to) = if diff > 0.0 { (i, j) } else { (j, i) };
+                    deltas[from] -= amount;
+                    deltas[to] += amount;
+                }
+            }
+        }
+        for (c, d) in grid.cells.iter_mut().zip(deltas.iter()) {
+            *c += d;
+        }
+    }
+}
+
+// Erode altitudes in place with hydraulic erosion followed by thermal erosion
+pub fn erode<R: Rng>(
+    rng: &mut R,
+    altitudes: &mut [Vec<u8>],
+    droplets: usize,
+    strength: f64,
+    thermal_iterations: usize,
+    talus: f64,
+    wrap: Wrap,
+) {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let mut grid = Grid {
+        width,
+        height,
+        cells: altitudes.iter().flatten().map(|a| f64::from(*a)).collect(),
+        wrap,
+    };
+
+    hydraulic(rng, &mut grid, droplets, strength);
+    thermal(&mut grid, thermal_iterations, talus);
+
+    for (y, row) in altitudes.iter_mut().enumerate() {
+        for (x, alt) in row.iter_mut().enumerate() {
+            *alt = grid.at(x, y).round().clamp(0.0, 99.0) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn cone(size: usize) -> Vec<Vec<u8>> {
+        let c = (size / 2) as isize;
+        (0..size as isize)
+            .map(|y| {
+                (0..size as isize)
+                    .map(|x| (99 - ((x - c).abs() + (y - c).abs()) * 4).max(0) as u8)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn thermal_erosion_flattens_steep_slopes() {
+        let mut alts = vec![vec![0, 99, 0]];
+        let mut rng = rand::thread_rng();
+        erode(&mut rng, &mut alts, 0, 0.0, 100, 4.0, Wrap::None);
+        let row = &alts[0];
+        assert!(row[1] < 99, "{:?}", row);
+        assert!(row[0] > 0 && row[2] > 0, "{:?}", row);
+        // Soil is moved, not lost
+        let sum: u32 = row.iter().map(|a| u32::from(*a)).sum();
+        assert!((97..=101).contains(&sum), "{:?}", row);
+    }
+
+    #[test]
+    fn hydraulic_erosion_changes_terrain() {
+        let orig = cone(41);
+        let mut alts = orig.clone();
+        let mut rng = rand::thread_rng();
+        erode(&mut rng, &mut alts, 2000, 0.5, 0, 4.0, Wrap::None);
+        assert_ne!(orig, alts);
+        assert!(alts.iter().flatten().all(|a| *a < 100));
+    }
+
+    #[test]
+    fn erosion_is_deterministic() {
+        let mut a1 = cone(31);
+        let mut a2 = cone(31);
+        let mut r1 = rand::rngs::StdRng::seed_from_u64(42);
+        let mut r2 = rand::rngs::StdRng::seed_from_u64(42);
+        erode(&mut r1, &mut a1, 500, 0.3, 5, 4.0, Wrap::None);
+        erode(&mut r2, &mut a2, 500, 0.3, 5, 4.0, Wrap::None);
+        assert_eq!(a1, a2);
+    }
+
+    #[test]
+    fn erode_small_boards() {
+        let mut rng = rand::thread_rng();
+        for alts in &mut [vec![], vec![vec![10]], vec![vec![10, 20]]] {
+            for wrap in &[Wrap::None, Wrap::Both] {
+                erode(&mut rng, alts, 100, 0.5, 5, 1.0, *wrap);
+            }
+        }
+    }
+
+    #[test]
+    fn erosion_across_edges() {
+        let mut rng = rand::thread_rng();
+        let mut alts = vec![vec![99, 0, 0, 0, 0]];
+        erode(&mut rng, &mut alts, 0, 0.0, 1, 4.0, Wrap::None);
+        assert_eq!(alts[0][4], 0, "{:?}", alts);
+
+        let mut alts = vec![vec![99, 0, 0, 0, 0]];
+        erode(&mut rng, &mut alts, 0, 0.0, 1, 4.0, Wrap::Horizontal);
+        assert!(alts[0][4] > 0, "{:?}", alts);
+        assert_eq!(alts[0][1], alts[0][4], "{:?}", alts);
+
+        // Droplets run across edges
+        let mut alts = cone(31);
+        for row in alts.iter_mut() {
+            row.rotate_left(15);
+        }
+        erode(&mut rng, &mut alts, 2000, 0.5, 0, 4.0, Wrap::Both);
+        assert!(alts.iter().flatten().all(|a| *a < 100));
+    }
+}
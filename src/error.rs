@@ -1,5 +1,6 @@
 //! Provides error type to represent all kinds of errors which may occur while world map generations.
 
+use png;
 use serde_json;
 
 use std::fmt;
@@ -16,6 +17,16 @@ pub enum Error {
     CannotDetermineTermsize,
     /// A fatal error when a generated board cannot be serialized into JSON.
     NotJsonSerializable(serde_json::Error),
+    /// An error when a board cannot be encoded into PNG image.
+    CannotEncodePng(png::EncodingError),
+    /// An error when a heightmap image cannot be read or its altitudes are invalid.
+    InvalidHeightmap(String),
+    /// An error when an input is not a board in binary format.
+    InvalidBinaryFormat(String),
+    /// An error when a board cannot be generated again since its metadata is missing.
+    CannotRegenerate(String),
+    /// An error when a board cannot be encoded into glTF since it has no triangle.
+    CannotEncodeGltf(String),
 }
 
 impl fmt::Display for Error {
@@ -24,6 +35,11 @@ impl fmt::Display for Error {
             Error::IoError(e) => write!(f, "{}", e),
             Error::CannotDetermineTermsize => write!(f, "Cannot determine terminal size"),
             Error::NotJsonSerializable(err) => write!(f, "Cannot serialize as JSON: {}", err),
+            Error::CannotEncodePng(err) => write!(f, "Cannot encode as PNG: {}", err),
+            Error::InvalidHeightmap(msg) => write!(f, "Invalid heightmap: {}", msg),
+            Error::InvalidBinaryFormat(msg) => write!(f, "Invalid binary format: {}", msg),
+            Error::CannotRegenerate(msg) => write!(f, "Cannot regenerate board: {}", msg),
+            Error::CannotEncodeGltf(msg) => write!(f, "Cannot encode as glTF: {}", msg),
         }
     }
 }
@@ -40,6 +56,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<png::EncodingError> for Error {
+    fn from(err: png::EncodingError) -> Error {
+        Error::CannotEncodePng(err)
+    }
+}
+
 /// Reprensents a value or an error in `world_map_gen` package. Similar to `std::io::Error` for
 /// `std::io` package.
 pub type Result<T> = std::result::Result<T, Error>;
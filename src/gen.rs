@@ -37,6 +37,23 @@
 //! assert_eq!(board.width(), 3);
 //! assert_eq!(board.height(), 4);
 //! ```
+//!
+//! Generated boards have metadata (seed, resolution, parameters and crate version) of their
+//! generation. The metadata is serialized with the board so that the same board can be generated
+//! again from its JSON.
+//!
+//! ```rust
+//! use world_map_gen::board::Board;
+//! use world_map_gen::gen::RandomBoardGen;
+//!
+//! let board = RandomBoardGen::from_seed(42).gen_large(150, 150);
+//! let json = serde_json::to_string(&board).unwrap();
+//!
+//! let loaded: Board = serde_json::from_str(&json).unwrap();
+//! let board2 = RandomBoardGen::regenerate(&loaded).unwrap();
+//! assert_eq!(board, board2);
+//! assert_eq!(board.metadata(), board2.metadata());
+//! ```
 
 use rand;
 use term_size;
@@ -44,11 +61,13 @@ use term_size;
 extern crate wasm_bindgen;
 
 use self::rand::{rngs, Rng};
-use crate::board::Board;
+use crate::biome::{sea_level_for_water_ratio, BiomeTable};
+use crate::board::{Board, Layout, Neighborhood, Wrap};
+use crate::climate::ClimateParams;
 use crate::error::{Error, Result};
-use crate::land::LandKind;
 use crate::large_gen::LargeBoardGen;
 use crate::middle_gen::MiddleBoardGen;
+use crate::terrain::{ErosionParams, Terrain};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
@@ -72,9 +91,20 @@ fn board_size(width: Option<usize>, height: Option<usize>) -> Result<(usize, usi
     Ok((width.unwrap_or(w), height.unwrap_or(h)))
 }
 
+// Determine resolution from size of a board
+fn auto_resolution(width: usize, height: usize) -> Resolution {
+    if width < 15 && height < 15 {
+        Resolution::Low
+    } else if width < 120 && height < 120 {
+        Resolution::Middle
+    } else {
+        Resolution::High
+    }
+}
+
 /// Resolution of the board.
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Resolution {
     /// Low resolution. There are limited kinds of cells in generated board.
     Low,
@@ -85,9 +115,124 @@ pub enum Resolution {
     High,
 }
 
+/// Parameters to tune generation of middle and high resolution boards. All parameters are
+/// optional. When a parameter is `None`, its value is calculated from the board size (and random
+/// numbers) as the generator does by default. The struct is serializable with serde so it can be
+/// saved to and loaded from a JSON file.
+///
+/// ```rust
+/// use world_map_gen::gen::{GenConfig, RandomBoardGen};
+///
+/// let config = GenConfig {
+///     num_tops: Some(10),
+///     down_rate: Some(8),
+///     ..GenConfig::default()
+/// };
+/// let mut gen = RandomBoardGen::from_seed(42).with_config(config);
+/// let board = gen.gen_middle(40, 30);
+/// assert_eq!(board.width(), 40);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenConfig {
+    /// Number of towns put on a middle resolution board.
+    pub num_towns: Option<usize>,
+    /// Max number of towns put on a high resolution board.
+    pub max_towns: Option<usize>,
+    /// Number of tops of mountains.
+    pub num_tops: Option<usize>,
+    /// Max number of rivers on a high resolution board. Rivers flow from tops of mountains down to
+    /// the sea or out of the board. When `None`, a river flows from each top.
+    pub num_rivers: Option<usize>,
+    /// Minimum number of cells of a lake on a high resolution board. Closed basins above sea level
+    /// are filled with water and become lakes. Basins smaller than this are left as they are. When
+    /// `None`, 16 is used.
+    pub lake_min_area: Option<usize>,
+    /// Minimum distance between towns on a middle resolution board.
+    pub min_distance: Option<usize>,
+    /// Minimum move cost between towns on a high resolution board.
+    pub town_min_cost: Option<usize>,
+    /// Max move cost of a connection between two towns on a high resolution board. Towns which are
+    /// more distant than this value are not connected by a path.
+    pub conn_max_cost: Option<usize>,
+    /// How much altitude goes down per cell on slopes of mountains. Larger value makes steeper
+    /// slopes and smaller mountains.
+    pub down_rate: Option<u8>,
+    /// Table to classify cells into land kinds by their altitudes. When `None`, the preset table
+    /// for the resolution (`BiomeTable::preset()`) is used.
+    pub biomes: Option<BiomeTable>,
+    /// Sea level as altitude in 0..=99. Cells whose altitudes are lower than or equal to the sea
+    /// level become water. Water bands and other bands of the biome table are shifted to match the
+    /// sea level. Lower sea level makes a big continent and higher sea level makes islands.
+    pub sea_level: Option<u8>,
+    /// Target ratio of water cells in a board in 0.0..=1.0. When this value is set, sea level is
+    /// solved from the histogram of generated altitudes so that the ratio of water cells is closest
+    /// to this value. It takes precedence over `sea_level`.
+    pub water_ratio: Option<f64>,
+    /// Algorithm to generate altitudes of cells. `down_rate` is only used by `Terrain::Slope`.
+    pub terrain: Terrain,
+    /// Climate model to classify land into biomes such as desert and jungle by temperature and
+    /// moisture on middle and high resolution boards. When `None`, land is classified only by
+    /// altitude.
+    pub climate: Option<ClimateParams>,
+    /// Erosion simulation applied to generated altitudes on middle and high resolution boards.
+    /// When `None`, altitudes are not eroded.
+    pub erosion: Option<ErosionParams>,
+    /// How middle and high resolution boards wrap around at their edges. On wrapped boards,
+    /// terrain, rivers, climate, distances between towns and paths continue across connected
+    /// edges so that the edges tile seamlessly. Cells of low resolution boards are independent so
+    /// they are always seamless.
+    pub wrap: Wrap,
+    /// Layout of cells of generated boards. On hex layout, slopes of mountains spread over 6
+    /// neighbors and distances between towns and paths are measured on the hex grid.
+    pub layout: Layout,
+    /// Cells to move to in one step on square layout. With `Neighborhood::Eight`, paths on high
+    /// resolution boards also go diagonally so that they look natural instead of staircases.
+    /// Diagonal steps cost more than straight ones and paths never cut a corner of water.
+    /// Distances between towns are measured in the neighborhood as well. It is ignored on hex
+    /// layout.
+    pub neighborhood: Neighborhood,
+}
+
+impl GenConfig {
+    /// Returns a table to classify cells for generating a board of the given resolution. When
+    /// `sea_level` is set, the water bands of the table are shifted to the sea level. Note that
+    /// `water_ratio` is not considered since it depends on generated altitudes.
+    pub fn biome_table(&self, resolution: Resolution) -> BiomeTable {
+        let table = match &self.biomes {
+            Some(table) => table.clone(),
+            None => BiomeTable::preset(resolution),
+        };
+        match self.sea_level {
+            Some(level) => table.with_sea_level(level),
+            None => table,
+        }
+    }
+}
+
+/// Metadata of a board generated by `RandomBoardGen`. It records how the board was generated.
+/// `board::Board` has it and it is serialized with the board.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Seed of the random number generator. The seed can reproduce only the first board generated
+    /// by a generator created with `RandomBoardGen::from_seed()`. Otherwise it is `None`.
+    pub seed: Option<u64>,
+    /// Resolution of the board.
+    pub resolution: Resolution,
+    /// Parameters used for generating the board.
+    pub config: GenConfig,
+    /// Version of this crate which generated the board. Boards generated from the same seed and
+    /// parameters may be different among versions.
+    pub version: String,
+}
+
 /// Random world map board generator
 pub struct RandomBoardGen<R: Rng> {
     rng: R,
+    config: GenConfig,
+    // Seed is taken by the first generated board since the following boards cannot be reproduced
+    // only from the seed
+    seed: Option<u64>,
 }
 
 impl RandomBoardGen<rngs::StdRng> {
@@ -96,8 +241,30 @@ impl RandomBoardGen<rngs::StdRng> {
     pub fn from_seed(seed: u64) -> Self {
         RandomBoardGen {
             rng: rand::SeedableRng::seed_from_u64(seed),
+            config: GenConfig::default(),
+            seed: Some(seed),
         }
     }
+
+    /// Generate the same board as the given board again from its metadata. The size of the board
+    /// is also taken from the given board. When the board has no metadata or its seed is unknown,
+    /// this method returns an error. Note that the result may be different when the board was
+    /// generated by another version of this crate.
+    pub fn regenerate(board: &Board) -> Result<Board> {
+        let metadata = board.metadata().ok_or_else(|| {
+            Error::CannotRegenerate("Board has no metadata of generation".to_string())
+        })?;
+        let seed = metadata.seed.ok_or_else(|| {
+            Error::CannotRegenerate("Seed of random number generator is unknown".to_string())
+        })?;
+        let mut gen = RandomBoardGen::from_seed(seed).with_config(metadata.config.clone());
+        let (width, height) = (board.width(), board.height());
+        Ok(match metadata.resolution {
+            Resolution::Low => gen.gen_small(width, height),
+            Resolution::Middle => gen.gen_middle(width, height),
+            Resolution::High => gen.gen_large(width, height),
+        })
+    }
 }
 
 impl Default for RandomBoardGen<rngs::ThreadRng> {
@@ -106,11 +273,41 @@ impl Default for RandomBoardGen<rngs::ThreadRng> {
     fn default() -> Self {
         RandomBoardGen {
             rng: rand::thread_rng(),
+            config: GenConfig::default(),
+            seed: None,
         }
     }
 }
 
 impl<R: Rng> RandomBoardGen<R> {
+    /// Set parameters for generating middle and high resolution boards.
+    pub fn with_config(mut self, config: GenConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Returns a reference to parameters for generating boards.
+    #[inline]
+    pub fn config(&self) -> &GenConfig {
+        &self.config
+    }
+
+    /// Returns a mutable reference to parameters for generating boards. It is useful to change
+    /// parameters between generations.
+    #[inline]
+    pub fn config_mut(&mut self) -> &mut GenConfig {
+        &mut self.config
+    }
+
+    fn metadata(&mut self, resolution: Resolution) -> Metadata {
+        Metadata {
+            seed: self.seed.take(),
+            resolution,
+            config: self.config.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
     /// Most generic method to generate a random map. When resolution is `None`, the resolution is
     /// determined from its size. When width and/or height are `None` they will be determined from
     /// the terminal size. In the case, when terminal size cannot be obtained, this method returns
@@ -120,7 +317,7 @@ impl<R: Rng> RandomBoardGen<R> {
         resolution: Option<Resolution>,
         width: Option<usize>,
         height: Option<usize>,
-    ) -> Result<Board<'static>> {
+    ) -> Result<Board> {
         let (width, height) = board_size(width, height)?;
         Ok(match resolution {
             Some(Resolution::Low) => self.gen_small(width, height),
@@ -135,38 +332,91 @@ impl<R: Rng> RandomBoardGen<R> {
     /// - When width and height are lower than 15: low resolution
     /// - When width and height are lower than 120: middle resolution
     /// - Otherwise: high resolution
-    pub fn gen_auto(&mut self, width: usize, height: usize) -> Board<'static> {
-        if width < 15 && height < 15 {
-            self.gen_small(width, height)
-        } else if width < 120 && height < 120 {
-            self.gen_middle(width, height)
-        } else {
-            self.gen_large(width, height)
+    pub fn gen_auto(&mut self, width: usize, height: usize) -> Board {
+        match auto_resolution(width, height) {
+            Resolution::Low => self.gen_small(width, height),
+            Resolution::Middle => self.gen_middle(width, height),
+            Resolution::High => self.gen_large(width, height),
         }
     }
 
     /// Generate a low resolution random map of given width and height.
-    pub fn gen_small(&mut self, width: usize, height: usize) -> Board<'static> {
-        Board::build(width, height, |_, _| {
-            let alt = self.rng.gen_range(0, 100);
-            match alt {
-                0..=15 => LandKind::Sea.preset(alt),
-                16..=55 => LandKind::Plain.preset(alt),
-                56..=85 => LandKind::Forest.preset(alt),
-                86..=99 => LandKind::Mountain.preset(alt),
-                _ => unreachable!(),
-            }
-        })
+    pub fn gen_small(&mut self, width: usize, height: usize) -> Board {
+        let rng = &mut self.rng;
+        let altitudes = (0..width * height)
+            .map(|_| rng.gen_range(0, 100))
+            .collect::<Vec<u8>>();
+        let mut board = self.small_board(width, height, altitudes);
+        board.set_metadata(Some(self.metadata(Resolution::Low)));
+        board
+    }
+
+    fn small_board(&self, width: usize, height: usize, altitudes: Vec<u8>) -> Board {
+        let mut biomes = self.config.biome_table(Resolution::Low);
+        if let Some(ratio) = self.config.water_ratio {
+            biomes = biomes.with_sea_level(sea_level_for_water_ratio(altitudes.clone(), ratio));
+        }
+        let mut board = Board::build(width, height, |x, y| {
+            let alt = altitudes[y * width + x];
+            biomes.land_kind(alt).preset(alt)
+        });
+        board.set_layout(self.config.layout);
+        board
     }
 
     /// Generate a middle resolution random map of given width and height.
-    pub fn gen_middle(&mut self, width: usize, height: usize) -> Board<'static> {
-        MiddleBoardGen::new(&mut self.rng, width, height).gen()
+    pub fn gen_middle(&mut self, width: usize, height: usize) -> Board {
+        let mut board = MiddleBoardGen::new(&mut self.rng, width, height, &self.config).gen();
+        board.set_metadata(Some(self.metadata(Resolution::Middle)));
+        board
     }
 
     /// Generate a large resolution random map of given width and height.
-    pub fn gen_large(&mut self, width: usize, height: usize) -> Board<'static> {
-        LargeBoardGen::new(&mut self.rng, width, height).gen()
+    pub fn gen_large(&mut self, width: usize, height: usize) -> Board {
+        let mut board = LargeBoardGen::new(&mut self.rng, width, height, &self.config).gen();
+        board.set_metadata(Some(self.metadata(Resolution::High)));
+        board
+    }
+
+    /// Generate a map on the given altitudes instead of random terrain. `altitudes` are rows of
+    /// cells from top to bottom and each altitude must be in 0..=99. `heightmap` module can read
+    /// them from a heightmap image. Cells are classified into land kinds and towns and paths are
+    /// put on them as the resolution does. When resolution is `None`, the resolution is
+    /// determined from the size. When rows have different lengths or some altitude is out of
+    /// range, this method returns an error. The board has no metadata since it cannot be generated
+    /// again without the heightmap.
+    pub fn gen_from_heightmap(
+        &mut self,
+        resolution: Option<Resolution>,
+        altitudes: Vec<Vec<u8>>,
+    ) -> Result<Board> {
+        let height = altitudes.len();
+        let width = altitudes.first().map(Vec::len).unwrap_or(0);
+        if altitudes.iter().any(|row| row.len() != width) {
+            return Err(Error::InvalidHeightmap(
+                "All rows must have the same number of cells".to_string(),
+            ));
+        }
+        if let Some(alt) = altitudes.iter().flatten().find(|a| **a > 99) {
+            return Err(Error::InvalidHeightmap(format!(
+                "Altitude {} is out of range 0..=99",
+                alt
+            )));
+        }
+
+        let resolution = resolution.unwrap_or_else(|| auto_resolution(width, height));
+        // Following boards cannot be reproduced from the seed
+        self.seed = None;
+        Ok(match resolution {
+            Resolution::Low => {
+                let altitudes = altitudes.into_iter().flatten().collect();
+                self.small_board(width, height, altitudes)
+            }
+            Resolution::Middle => MiddleBoardGen::new(&mut self.rng, width, height, &self.config)
+                .gen_from_altitudes(altitudes),
+            Resolution::High => LargeBoardGen::new(&mut self.rng, width, height, &self.config)
+                .gen_from_altitudes(altitudes),
+        })
     }
 }
 
@@ -263,8 +513,11 @@ mod tests {
                 let p = Pos { x, y };
                 let l = &b[p];
                 assert!(
-                    [DeepSea, Sea, Plain, Forest, Mountain, Highland, Top, Town, Path]
-                        .contains(&l.kind),
+                    [
+                        DeepSea, Sea, Plain, Forest, Mountain, Highland, Top, Town, Path, River,
+                        Lake,
+                    ]
+                    .contains(&l.kind),
                     "{:?} at {:?}",
                     l,
                     p
@@ -287,4 +540,562 @@ mod tests {
         assert!(found_town);
         assert!(found_path);
     }
+
+    #[test]
+    fn gen_large_on_small_stack() {
+        // Slopes of mountains spread widely with gentle down rate. Generation must not depend on
+        // stack size even if a slope covers entire board. Note that depth-first search on a slope
+        // of this board exceeds thousands of cells, which overflowed the small stack when slopes
+        // were created recursively.
+        let config = GenConfig {
+            down_rate: Some(2),
+            num_tops: Some(3),
+            max_towns: Some(0),
+            ..GenConfig::default()
+        };
+        let (w, h) = (300, 300);
+        let b = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                RandomBoardGen::from_seed(42)
+                    .with_config(config)
+                    .gen_large(w, h)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(b.width(), w);
+        assert_eq!(b.height(), h);
+        assert_eq!(b.iter().filter(|l| l.kind == Top).count(), 3);
+    }
+
+    #[test]
+    fn gen_with_config() {
+        let config = GenConfig {
+            num_towns: Some(5),
+            min_distance: Some(1),
+            num_tops: Some(2),
+            ..GenConfig::default()
+        };
+        let mut g = RandomBoardGen::from_seed(42).with_config(config.clone());
+        assert_eq!(g.config(), &config);
+        let b = g.gen_middle(48, 36);
+        assert_eq!(b.iter().filter(|l| l.kind == Top).count(), 2);
+        assert_eq!(b.iter().filter(|l| l.kind == Town).count(), 5);
+
+        g.config_mut().max_towns = Some(0);
+        let b = g.gen_large(150, 150);
+        assert!(b.iter().all(|l| l.kind != Town && l.kind != Path));
+    }
+
+    #[test]
+    fn gen_with_zero_down_rate() {
+        let config = GenConfig {
+            down_rate: Some(0),
+            ..GenConfig::default()
+        };
+        let b = RandomBoardGen::default()
+            .with_config(config)
+            .gen_middle(20, 20);
+        assert!(b.iter().all(|l| l.altitude == 99));
+    }
+
+    #[test]
+    fn gen_with_biome_table() {
+        let config = GenConfig {
+            biomes: Some(BiomeTable::new(vec![(40, Sea), (99, Plain)])),
+            ..GenConfig::default()
+        };
+        let mut g = RandomBoardGen::default().with_config(config);
+        let b = g.gen_small(10, 10);
+        assert!(b.iter().all(|l| [Sea, Plain].contains(&l.kind)), "{:?}", b);
+
+        let b = g.gen_middle(40, 40);
+        for l in b.iter() {
+            match l.kind {
+                Sea => assert!(l.altitude <= 40),
+                Plain | Town => assert!(l.altitude > 40),
+                Top => assert_eq!(l.altitude, 99),
+                k => panic!("unexpected kind {:?}", k),
+            }
+        }
+
+        let b = g.gen_large(150, 150);
+        assert!(b
+            .iter()
+            .all(|l| [Sea, Plain, Top, Town, Path, River, Lake].contains(&l.kind)));
+    }
+
+    #[test]
+    fn gen_large_without_plains() {
+        let config = GenConfig {
+            biomes: Some(BiomeTable::new(vec![(99, Sea)])),
+            ..GenConfig::default()
+        };
+        let b = RandomBoardGen::default()
+            .with_config(config)
+            .gen_large(150, 150);
+        assert!(b.iter().all(|l| l.kind != Town && l.kind != Path));
+    }
+
+    #[test]
+    fn gen_with_sea_level() {
+        fn water_ratio(b: &Board) -> f64 {
+            let water = b.iter().filter(|l| l.kind.is_water()).count();
+            water as f64 / (b.width() * b.height()) as f64
+        }
+
+        let config = GenConfig {
+            sea_level: Some(60),
+            ..GenConfig::default()
+        };
+        let mut g = RandomBoardGen::from_seed(1).with_config(config);
+        let b = g.gen_middle(60, 60);
+        for l in b.iter() {
+            if l.kind == Sea {
+                assert!(l.altitude <= 60, "{:?}", l);
+            } else if l.kind != Top {
+                assert!(l.altitude > 60, "{:?}", l);
+            }
+        }
+
+        for &ratio in &[0.2, 0.5, 0.8] {
+            let config = GenConfig {
+                water_ratio: Some(ratio),
+                ..GenConfig::default()
+            };
+            // Ratio varies by seed since cells at the same altitude are on the same side of the
+            // sea level
+            let mut g = RandomBoardGen::from_seed(1).with_config(config);
+            let b = g.gen_small(14, 14);
+            assert!((water_ratio(&b) - ratio).abs() < 0.1, "{}", ratio);
+            let b = g.gen_middle(60, 60);
+            assert!((water_ratio(&b) - ratio).abs() < 0.1, "{}", ratio);
+            let b = g.gen_large(150, 150);
+            let r = water_ratio(&b);
+            // Paths may be built on water cells
+            assert!((r - ratio).abs() < 0.1, "{} vs {}", r, ratio);
+        }
+    }
+
+    #[test]
+    fn gen_noise_terrain() {
+        use crate::terrain::NoiseParams;
+
+        let config = GenConfig {
+            terrain: Terrain::Noise(NoiseParams::default()),
+            ..GenConfig::default()
+        };
+        let mut g = RandomBoardGen::from_seed(42).with_config(config.clone());
+        let b = g.gen_middle(60, 60);
+        assert!(b.iter().any(|l| l.kind == Top));
+        assert!(b.iter().any(|l| l.kind == Town));
+
+        let b = g.gen_large(150, 150);
+        assert!(b.iter().any(|l| l.kind == Top));
+        assert!(b.iter().any(|l| l.kind == Town));
+        assert!(b.iter().any(|l| l.kind == Path));
+
+        // Deterministic with the same seed
+        let b1 = RandomBoardGen::from_seed(1)
+            .with_config(config.clone())
+            .gen_middle(30, 30);
+        let b2 = RandomBoardGen::from_seed(1)
+            .with_config(config)
+            .gen_middle(30, 30);
+        assert_eq!(b1, b2);
+    }
+
+    #[test]
+    fn gen_diamond_square_terrain() {
+        use crate::terrain::DiamondSquareParams;
+
+        let mut g = RandomBoardGen::from_seed(3);
+        let b = g.gen_large(150, 150);
+        assert!(b.iter().any(|l| l.kind == Top));
+
+        // Terrain can be switched per call
+        g.config_mut().terrain = Terrain::DiamondSquare(DiamondSquareParams { roughness: 0.6 });
+        let b = g.gen_large(150, 150);
+        assert!(b.iter().any(|l| l.kind == Top));
+        assert!(b.iter().any(|l| l.kind == Town));
+        assert!(b.iter().any(|l| l.kind == Path));
+        let b = g.gen_middle(50, 50);
+        assert!(b.iter().any(|l| l.kind == Top));
+    }
+
+    #[test]
+    fn gen_rivers() {
+        let mut g = RandomBoardGen::from_seed(7);
+        let b = g.gen_large(150, 150);
+        let rivers = b.iter().filter(|l| l.kind == River).count();
+        assert!(rivers > 0);
+        // Each river cell is connected to another river cell, a bridge, a top or water
+        for y in 0..b.height() {
+            for x in 0..b.width() {
+                if b.at(x, y).kind != River {
+                    continue;
+                }
+                let p = Pos { x, y };
+                let connected = [(0, -1), (-1, 0), (1, 0), (0, 1)].iter().any(|(dx, dy)| {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    nx >= 0
+                        && ny >= 0
+                        && (nx as usize) < b.width()
+                        && (ny as usize) < b.height()
+                        && {
+                            let k = b.at(nx as usize, ny as usize).kind;
+                            [River, Lake, Path, Top].contains(&k) || k.is_water()
+                        }
+                });
+                assert!(connected, "isolated river at {:?}", p);
+            }
+        }
+
+        g.config_mut().num_rivers = Some(0);
+        let b = g.gen_large(150, 150);
+        assert!(b.iter().all(|l| l.kind != River));
+    }
+
+    #[test]
+    fn gen_lakes() {
+        let config = GenConfig {
+            lake_min_area: Some(1),
+            ..GenConfig::default()
+        };
+        let mut g = RandomBoardGen::from_seed(11).with_config(config);
+        let b = g.gen_large(120, 40);
+        assert!(b.iter().any(|l| l.kind == Lake));
+
+        g.config_mut().lake_min_area = Some(usize::MAX);
+        let b = g.gen_large(120, 40);
+        assert!(b.iter().all(|l| l.kind != Lake));
+    }
+
+    #[test]
+    fn gen_with_climate() {
+        use crate::climate::ClimateParams;
+
+        let config = GenConfig {
+            climate: Some(ClimateParams::default()),
+            ..GenConfig::default()
+        };
+        let climates = [Desert, Tundra, Snow, Swamp, Jungle, Savanna];
+        let mut g = RandomBoardGen::from_seed(5).with_config(config);
+        let b = g.gen_large(120, 50);
+        assert!(b.iter().any(|l| climates.contains(&l.kind)));
+        assert!(b.iter().any(|l| l.kind == Town));
+        let b = g.gen_middle(60, 40);
+        assert!(b.iter().any(|l| climates.contains(&l.kind)));
+
+        // Frozen world
+        g.config_mut().climate = Some(ClimateParams {
+            equator_temperature: -30.0,
+            ..ClimateParams::default()
+        });
+        let b = g.gen_large(120, 50);
+        assert!(b.iter().all(|l| ![Plain, Forest, Jungle].contains(&l.kind)));
+    }
+
+    #[test]
+    fn gen_with_erosion() {
+        let config = GenConfig {
+            erosion: Some(ErosionParams::default()),
+            ..GenConfig::default()
+        };
+        let b1 = RandomBoardGen::from_seed(5)
+            .with_config(config.clone())
+            .gen_large(100, 100);
+        let b2 = RandomBoardGen::from_seed(5)
+            .with_config(config.clone())
+            .gen_middle(40, 40);
+        assert!(b1.iter().any(|l| l.kind == Top));
+        assert!(b2.iter().any(|l| l.kind == Town));
+
+        // Eroded altitudes are deterministic with the same seed
+        let alts = |b: &Board| b.iter().map(|l| l.altitude).collect::<Vec<_>>();
+        let b3 = RandomBoardGen::from_seed(5)
+            .with_config(config)
+            .gen_large(100, 100);
+        assert_eq!(alts(&b1), alts(&b3));
+        let b4 = RandomBoardGen::from_seed(5).gen_large(100, 100);
+        assert_ne!(alts(&b1), alts(&b4));
+    }
+
+    #[test]
+    fn gen_from_heightmap() {
+        // A cone at the center surrounded by sea
+        let cone = |w: usize, h: usize| {
+            (0..h)
+                .map(|y| {
+                    (0..w)
+                        .map(|x| {
+                            let d = (x as isize - w as isize / 2).abs()
+                                + (y as isize - h as isize / 2).abs();
+                            (99 - d * 99 / (w / 2 + h / 2) as isize) as u8
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let alts = |b: &Board| {
+            b.rows()
+                .map(|row| row.iter().map(|l| l.altitude).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+
+        let mut g = RandomBoardGen::from_seed(3);
+        for &(w, h, res) in &[
+            (10, 8, Resolution::Low),
+            (60, 40, Resolution::Middle),
+            (120, 80, Resolution::High),
+        ] {
+            let heightmap = cone(w, h);
+            let b = g.gen_from_heightmap(Some(res), heightmap.clone()).unwrap();
+            assert_eq!((b.width(), b.height()), (w, h));
+            assert!(b.at(0, 0).kind.is_water(), "{:?}", res);
+            if res != Resolution::High {
+                // High resolution boards carve altitudes along rivers
+                assert_eq!(alts(&b), heightmap, "{:?}", res);
+            }
+            if res != Resolution::Low {
+                assert!(b.iter().any(|l| l.kind == Top), "{:?}", res);
+                assert!(b.iter().any(|l| l.kind == Town), "{:?}", res);
+            }
+
+            // Resolution is determined by size
+            let b = g.gen_from_heightmap(None, heightmap).unwrap();
+            assert_eq!((b.width(), b.height()), (w, h));
+        }
+
+        for invalid in &[vec![vec![1, 2], vec![3]], vec![vec![100]]] {
+            match g.gen_from_heightmap(None, invalid.clone()) {
+                Err(Error::InvalidHeightmap(_)) => {}
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_partial_config() {
+        let config: GenConfig = serde_json::from_str(r#"{"num_tops": 3}"#).unwrap();
+        assert_eq!(
+            config,
+            GenConfig {
+                num_tops: Some(3),
+                ..GenConfig::default()
+            }
+        );
+
+        let config: GenConfig =
+            serde_json::from_str(r#"{"terrain": {"Noise": {"octaves": 3}}}"#).unwrap();
+        match config.terrain {
+            Terrain::Noise(p) => {
+                assert_eq!(p.octaves, 3);
+                assert_eq!(p.lacunarity, 2.0);
+            }
+            t => panic!("unexpected terrain {:?}", t),
+        }
+    }
+
+    #[test]
+    fn gen_wrapped_board() {
+        let (w, h) = (160, 120);
+        let terrains = vec![
+            Terrain::Slope,
+            Terrain::Noise(Default::default()),
+            Terrain::DiamondSquare(Default::default()),
+        ];
+        for terrain in terrains {
+            for &wrap in &[Wrap::Horizontal, Wrap::Both] {
+                let config = GenConfig {
+                    terrain: terrain.clone(),
+                    wrap,
+                    town_min_cost: Some(30),
+                    ..GenConfig::default()
+                };
+                let b = RandomBoardGen::from_seed(3)
+                    .with_config(config)
+                    .gen_large(w, h);
+                let alt = |x: usize, y: usize| i32::from(b.at(x, y).altitude);
+
+                // Altitudes across the connected edges are as continuous as inside the board
+                let seam: i32 = (0..h).map(|y| (alt(0, y) - alt(w - 1, y)).abs()).sum();
+                let inside: i32 = (0..h)
+                    .map(|y| (alt(w / 2, y) - alt(w / 2 - 1, y)).abs())
+                    .sum();
+                assert!(
+                    seam <= inside * 2 + h as i32,
+                    "{:?} {:?}: {} {}",
+                    terrain,
+                    wrap,
+                    seam,
+                    inside
+                );
+                if wrap == Wrap::Both {
+                    let seam: i32 = (0..w).map(|x| (alt(x, 0) - alt(x, h - 1)).abs()).sum();
+                    let inside: i32 = (0..w)
+                        .map(|x| (alt(x, h / 2) - alt(x, h / 2 - 1)).abs())
+                        .sum();
+                    assert!(
+                        seam <= inside * 2 + w as i32,
+                        "{:?} {:?}: {} {}",
+                        terrain,
+                        wrap,
+                        seam,
+                        inside
+                    );
+                }
+
+                // Towns are distant even across the connected edges
+                let towns = (0..h)
+                    .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+                    .filter(|p| b[*p].kind == Town)
+                    .collect::<Vec<_>>();
+                assert!(!towns.is_empty(), "{:?} {:?}", terrain, wrap);
+                for (i, t) in towns.iter().enumerate() {
+                    for u in towns[i + 1..].iter() {
+                        assert!(wrap.move_cost(t, u, w, h) > 30, "{:?} {:?}", t, u);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gen_hex_board() {
+        let config = GenConfig {
+            layout: Layout::Hex,
+            town_min_cost: Some(20),
+            min_distance: Some(10),
+            ..GenConfig::default()
+        };
+        let mut gen = RandomBoardGen::from_seed(5).with_config(config);
+        for &(w, h, min) in &[(10, 10, 0), (80, 60, 10), (160, 120, 20)] {
+            let b = gen.gen_auto(w, h);
+            assert_eq!(b.layout(), Layout::Hex);
+
+            let cells = (0..h)
+                .flat_map(|y| (0..w).map(move |x| Pos { x, y }))
+                .collect::<Vec<_>>();
+            let towns = cells
+                .iter()
+                .filter(|p| b[**p].kind == Town)
+                .collect::<Vec<_>>();
+            for (i, t) in towns.iter().enumerate() {
+                for u in towns[i + 1..].iter() {
+                    let cost = Layout::Hex.move_cost(t, u, Wrap::None, Neighborhood::Four, w, h);
+                    assert!(cost > min, "{:?} {:?}", t, u);
+                }
+            }
+
+            // Paths are connected via hex neighbors
+            for p in cells.iter().filter(|p| b[**p].kind == Path) {
+                assert!(
+                    Layout::Hex
+                        .neighbors(p, Wrap::None, Neighborhood::Four, w, h)
+                        .any(|n| b[n].kind == Path || b[n].kind == Town),
+                    "Path at {:?} is isolated",
+                    p
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gen_diagonal_paths() {
+        let (w, h) = (200, 150);
+        let config = GenConfig {
+            neighborhood: Neighborhood::Eight,
+            ..GenConfig::default()
+        };
+        let b = RandomBoardGen::from_seed(11)
+            .with_config(config)
+            .gen_large(w, h);
+        let is_road = |p: Pos| b[p].kind == Path || b[p].kind == Town;
+        let neighbors = |p, n| Layout::Square.neighbors(&p, Wrap::None, n, w, h);
+
+        let mut found_diagonal = false;
+        for p in (0..h).flat_map(|y| (0..w).map(move |x| Pos { x, y })) {
+            if b[p].kind != Path {
+                continue;
+            }
+            assert!(
+                neighbors(p, Neighborhood::Eight).any(is_road),
+                "Path at {:?} is isolated",
+                p
+            );
+            if !neighbors(p, Neighborhood::Four).any(is_road) {
+                found_diagonal = true;
+            }
+        }
+        assert!(found_diagonal);
+    }
+
+    #[test]
+    fn regenerate_from_metadata() {
+        use crate::board::CompactBoard;
+
+        let configs = vec![
+            GenConfig::default(),
+            GenConfig {
+                terrain: Terrain::Noise(Default::default()),
+                climate: Some(Default::default()),
+                erosion: Some(Default::default()),
+                water_ratio: Some(0.3),
+                ..GenConfig::default()
+            },
+        ];
+        for config in configs {
+            for &(res, w, h) in &[
+                (Resolution::Low, 8, 6),
+                (Resolution::Middle, 60, 40),
+                (Resolution::High, 150, 130),
+            ] {
+                let b = RandomBoardGen::from_seed(7)
+                    .with_config(config.clone())
+                    .gen(Some(res), Some(w), Some(h))
+                    .unwrap();
+                let m = b.metadata().unwrap();
+                assert_eq!(m.seed, Some(7));
+                assert_eq!(m.resolution, res);
+                assert_eq!(m.config, config);
+                assert_eq!(m.version, env!("CARGO_PKG_VERSION"));
+
+                for json in &[
+                    serde_json::to_string(&b).unwrap(),
+                    serde_json::to_string(&CompactBoard(&b)).unwrap(),
+                ] {
+                    let loaded: Board = serde_json::from_str(json).unwrap();
+                    assert_eq!(loaded.metadata(), b.metadata());
+                    let regenerated = RandomBoardGen::regenerate(&loaded).unwrap();
+                    assert_eq!(regenerated, b, "{:?}", res);
+                    assert_eq!(regenerated.metadata(), b.metadata());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seed_is_only_for_first_board() {
+        let mut g = RandomBoardGen::from_seed(7);
+        assert_eq!(g.gen_small(3, 3).metadata().unwrap().seed, Some(7));
+        assert_eq!(g.gen_small(3, 3).metadata().unwrap().seed, None);
+
+        let b = RandomBoardGen::default().gen_middle(20, 20);
+        assert_eq!(b.metadata().unwrap().seed, None);
+        match RandomBoardGen::regenerate(&b) {
+            Err(Error::CannotRegenerate(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let b = RandomBoardGen::from_seed(7)
+            .gen_from_heightmap(None, vec![vec![10; 3]; 3])
+            .unwrap();
+        assert!(b.metadata().is_none());
+        match RandomBoardGen::regenerate(&b) {
+            Err(Error::CannotRegenerate(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 }
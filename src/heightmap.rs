@@ -0,0 +1,327 @@
+//! This module provides export and import of altitudes of boards as grayscale heightmap images.
+//!
+//! Altitudes in 0..=99 are scaled to the full range of gray levels so that they can be consumed by
+//! other tools such as game engines. PGM (both plain `P2` and raw `P5`) and grayscale PNG images
+//! are supported. Imported altitudes can be turned into a board with
+//! `gen::RandomBoardGen::gen_from_heightmap()`.
+//!
+//! ```rust
+//! use world_map_gen::gen::RandomBoardGen;
+//! use world_map_gen::heightmap;
+//!
+//! let mut gen = RandomBoardGen::from_seed(42);
+//! let board = gen.gen_middle(40, 30);
+//!
+//! // Export altitudes as 16-bit grayscale PNG image
+//! let mut png = Vec::<u8>::new();
+//! heightmap::write_png(&mut png, &board).unwrap();
+//!
+//! // Build another board on the same altitudes
+//! let altitudes = heightmap::read(png.as_slice()).unwrap();
+//! let board2 = gen.gen_from_heightmap(None, altitudes).unwrap();
+//! assert_eq!(board.width(), board2.width());
+//! assert_eq!(board.at(3, 4).altitude, board2.at(3, 4).altitude);
+//! ```
+
+use png;
+
+use crate::board::Board;
+use crate::error::{Error, Result};
+use std::io::{Read, Write};
+
+// Max value of altitude
+const MAX_ALTITUDE: u32 = 99;
+
+fn invalid<T>(msg: impl Into<String>) -> Result<T> {
+    Err(Error::InvalidHeightmap(msg.into()))
+}
+
+// Scale an altitude in 0..=99 to a gray level in 0..=max
+#[inline]
+fn to_gray(alt: u8, max: u32) -> u32 {
+    (u32::from(alt) * max + MAX_ALTITUDE / 2) / MAX_ALTITUDE
+}
+
+// Scale a gray level in 0..=max to an altitude in 0..=99
+#[inline]
+fn to_altitude(gray: u32, max: u32) -> u8 {
+    ((gray.min(max) * MAX_ALTITUDE + max / 2) / max) as u8
+}
+
+/// Write altitudes of cells in the board as raw PGM (`P5`) image with 8-bit gray levels. When
+/// writing to the writer failed, it returns an error.
+pub fn write_pgm<W: Write>(writer: &mut W, board: &Board) -> Result<()> {
+    write!(writer, "P5\n{} {}\n255\n", board.width(), board.height())?;
+    let data = board
+        .iter()
+        .map(|cell| to_gray(cell.altitude, 255) as u8)
+        .collect::<Vec<_>>();
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+/// Write altitudes of cells in the board as 16-bit grayscale PNG image. When encoding the image or
+/// writing to the writer failed, it returns an error.
+pub fn write_png<W: Write>(writer: W, board: &Board) -> Result<()> {
+    let mut data = Vec::with_capacity(board.width() * board.height() * 2);
+    for cell in board.iter() {
+        let gray = to_gray(cell.altitude, 0xffff) as u16;
+        data.extend_from_slice(&gray.to_be_bytes());
+    }
+
+    let mut encoder = png::Encoder::new(writer, board.width() as u32, board.height() as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Read altitudes from PGM image. Both plain (`P2`) and raw (`P5`) formats are supported. Gray
+/// levels are scaled to altitudes in 0..=99. Returned altitudes are rows of cells from top to
+/// bottom. When the image is broken, it returns an error.
+pub fn read_pgm<R: Read>(mut reader: R) -> Result<Vec<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    // Header consists of magic number, width, height and max gray level separated by whitespaces.
+    // Comments start with '#' and last until the end of line.
+    let mut idx = 0;
+    let mut header = Vec::with_capacity(4);
+    while header.len() < 4 {
+        while idx < bytes.len() {
+            match bytes[idx] {
+                b'#' => {
+                    while idx < bytes.len() && bytes[idx] != b'\n' {
+                        idx += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => idx += 1,
+                _ => break,
+            }
+        }
+        let start = idx;
+        while idx < bytes.len() && !bytes[idx].is_ascii_whitespace() {
+            idx += 1;
+        }
+        if start == idx {
+            return invalid("PGM header is incomplete");
+        }
+        header.push(&bytes[start..idx]);
+    }
+    // Exactly one whitespace separates the header and raw data
+    idx += 1;
+
+    let raw = match header[0] {
+        b"P5" => true,
+        b"P2" => false,
+        _ => return invalid("PGM image must start with 'P2' or 'P5'"),
+    };
+    let parse = |b: &[u8], what: &str| {
+        std::str::from_utf8(b)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(|| Error::InvalidHeightmap(format!("Invalid {} in PGM header", what)))
+    };
+    let width = parse(header[1], "width")? as usize;
+    let height = parse(header[2], "height")? as usize;
+    let max = parse(header[3], "max gray level")?;
+    if width == 0 || height == 0 {
+        return invalid(format!("Size of image {}x{} is empty", width, height));
+    }
+    let num_pixels = match width.checked_mul(height) {
+        Some(n) => n,
+        None => return invalid(format!("Size of image {}x{} is too large", width, height)),
+    };
+    if max == 0 || max > 0xffff {
+        return invalid(format!("Max gray level {} is out of range", max));
+    }
+
+    let grays = if raw {
+        let data = bytes.get(idx..).unwrap_or(&[]);
+        if max < 256 {
+            data.iter().map(|b| u32::from(*b)).collect::<Vec<_>>()
+        } else {
+            data.chunks_exact(2)
+                .map(|b| u32::from(u16::from_be_bytes([b[0], b[1]])))
+                .collect()
+        }
+    } else {
+        std::str::from_utf8(bytes.get(idx..).unwrap_or(&[]))
+            .ok()
+            .and_then(|s| {
+                s.split_ascii_whitespace()
+                    .map(|n| n.parse::<u32>().ok())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| Error::InvalidHeightmap("Invalid gray level in PGM data".to_string()))?
+    };
+    if grays.len() < num_pixels {
+        return invalid(format!(
+            "PGM data has {} pixels but {}x{} pixels are required",
+            grays.len(),
+            width,
+            height,
+        ));
+    }
+
+    Ok(grays[..num_pixels]
+        .chunks(width)
+        .map(|row| row.iter().map(|g| to_altitude(*g, max)).collect())
+        .collect())
+}
+
+/// Read altitudes from PNG image. Grayscale images with any bit depth are supported. For color
+/// images, average of RGB channels is used as gray level. Alpha channel is ignored. Gray levels
+/// are scaled to altitudes in 0..=99. Returned altitudes are rows of cells from top to bottom.
+/// When the image is broken, it returns an error.
+pub fn read_png<R: Read>(reader: R) -> Result<Vec<Vec<u8>>> {
+    let fail = |e: png::DecodingError| Error::InvalidHeightmap(format!("{}", e));
+    let mut decoder = png::Decoder::new(reader);
+    // Expand palettes and gray levels with less than 8 bits
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(fail)?;
+    let (width, height) = reader.info().size();
+    if width == 0 || height == 0 {
+        return invalid(format!("Size of image {}x{} is empty", width, height));
+    }
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(fail)?;
+
+    let (channels, colors) = match info.color_type {
+        png::ColorType::Grayscale => (1, 1),
+        png::ColorType::GrayscaleAlpha => (2, 1),
+        png::ColorType::Rgb | png::ColorType::Indexed => (3, 3),
+        png::ColorType::Rgba => (4, 3),
+    };
+    let (bytes, max) = match info.bit_depth {
+        png::BitDepth::Sixteen => (2, 0xffff),
+        _ => (1, 0xff),
+    };
+
+    let width = info.width as usize;
+    Ok(buf
+        .chunks(info.line_size)
+        .take(info.height as usize)
+        .map(|line| {
+            line.chunks(channels * bytes)
+                .take(width)
+                .map(|pixel| {
+                    let sum: u32 = pixel
+                        .chunks(bytes)
+                        .take(colors)
+                        .map(|c| match c {
+                            [hi, lo] => u32::from(u16::from_be_bytes([*hi, *lo])),
+                            _ => u32::from(c[0]),
+                        })
+                        .sum();
+                    to_altitude(sum / colors as u32, max)
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Read altitudes from PGM or PNG image. The format is detected from the content. When the format
+/// is unknown or the image is broken, it returns an error.
+pub fn read<R: Read>(mut reader: R) -> Result<Vec<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.starts_with(b"\x89PNG") {
+        read_png(bytes.as_slice())
+    } else if bytes.starts_with(b"P2") || bytes.starts_with(b"P5") {
+        read_pgm(bytes.as_slice())
+    } else {
+        invalid("Heightmap must be PGM or PNG image")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::land::LandKind;
+
+    fn board() -> Board {
+        Board::build(4, 3, |x, y| {
+            LandKind::Plain.preset(((x * 33 + y) % 100) as u8)
+        })
+    }
+
+    fn altitudes(board: &Board) -> Vec<Vec<u8>> {
+        board
+            .rows()
+            .map(|row| row.iter().map(|c| c.altitude).collect())
+            .collect()
+    }
+
+    #[test]
+    fn gray_level_conversion() {
+        for max in &[255, 0xffff, 99, 1000] {
+            for alt in 0..=99 {
+                assert_eq!(to_altitude(to_gray(alt, *max), *max), alt);
+            }
+            assert_eq!(to_gray(0, *max), 0);
+            assert_eq!(to_gray(99, *max), *max);
+        }
+    }
+
+    #[test]
+    fn pgm_round_trip() {
+        let b = board();
+        let mut buf = Vec::new();
+        write_pgm(&mut buf, &b).unwrap();
+        assert!(buf.starts_with(b"P5\n4 3\n255\n"));
+        assert_eq!(read(buf.as_slice()).unwrap(), altitudes(&b));
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let b = board();
+        let mut buf = Vec::new();
+        write_png(&mut buf, &b).unwrap();
+        assert_eq!(read(buf.as_slice()).unwrap(), altitudes(&b));
+    }
+
+    #[test]
+    fn read_plain_pgm() {
+        let pgm = b"P2\n# comment\n3 2\n# comment\n10\n0 5 10\n10 5 0\n";
+        let alts = read_pgm(&pgm[..]).unwrap();
+        assert_eq!(alts, vec![vec![0, 50, 99], vec![99, 50, 0]]);
+    }
+
+    #[test]
+    fn read_8bit_color_png() {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut buf, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&[255, 255, 255, 0, 0, 0, 0, 255])
+                .unwrap();
+        }
+        assert_eq!(read_png(buf.as_slice()).unwrap(), vec![vec![99, 0]]);
+    }
+
+    #[test]
+    fn broken_heightmaps() {
+        for input in &[
+            &b""[..],
+            b"P6\n1 1\n255\n\x00\x00\x00",
+            b"P5\n2 2\n255\n\x00",
+            b"P5\n2 2\n0\n\x00\x00\x00\x00",
+            b"P5\n0 0\n255\n",
+            b"P2\n3 0\n255\n",
+            b"P5\n4294967295 4294967295\n255\n\x00",
+            b"P2\n1 x\n255\n0",
+            b"P2\n1 1\n255\nfoo",
+            b"\x89PNG broken",
+        ] {
+            match read(*input) {
+                Err(Error::InvalidHeightmap(_)) => {}
+                r => panic!("Unexpected result {:?} for {:?}", r, input),
+            }
+        }
+    }
+}
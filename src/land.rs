@@ -11,7 +11,7 @@
 //!// Also your original cell can be defined
 //! let cell = Land {
 //!     kind: LandKind::Town,
-//!     char: "[]",
+//!     char: "[]".into(),
 //!     color: {
 //!         let mut c = termcolor::ColorSpec::new();
 //!         c.set_fg(Some(termcolor::Color::Black));
@@ -33,40 +33,43 @@ use termcolor;
 extern crate wasm_bindgen;
 
 use crate::color;
+use std::borrow::Cow;
 use termcolor::{Color, ColorSpec};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
-/// Represents one cell in a board
-#[derive(Debug, Clone, PartialEq)]
-pub struct Land<'a> {
+/// Represents one cell in a board. It is serializable and deserializable with serde.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Land {
     /// Kind of the cell like sea, plain, mountain, forest, ...
     pub kind: LandKind,
-    /// Character to represent the cell. It is mainly used for rendering the board to terminal
-    pub char: &'a str,
+    /// Character to represent the cell. It is mainly used for rendering the board to terminal.
+    /// Preset cells borrow static strings and custom cells can own strings created at runtime.
+    pub char: Cow<'static, str>,
     /// Color of the cell
+    #[serde(deserialize_with = "color::deserialize_color_spec")]
     pub color: ColorSpec,
     /// Altitude of the cell. It is 0 by default, and will be set on generating a map.
     pub altitude: u8,
 }
 
-impl<'a> serde::Serialize for Land<'a> {
+impl serde::Serialize for Land {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         use serde::ser::SerializeMap;
         let mut map = serializer.serialize_map(Some(4))?;
         map.serialize_entry("kind", &self.kind)?;
-        map.serialize_entry("char", self.char)?;
+        map.serialize_entry("char", &self.char)?;
         map.serialize_entry("color", &color::SerializableColorSpec(&self.color))?;
         map.serialize_entry("altitude", &self.altitude)?;
         map.end()
     }
 }
 
-impl Default for Land<'static> {
-    fn default() -> Land<'static> {
+impl Default for Land {
+    fn default() -> Land {
         Land {
             kind: LandKind::Plain,
-            char: "██",
+            char: Cow::Borrowed("██"),
             color: ColorSpec::default(),
             altitude: 0,
         }
@@ -79,15 +82,18 @@ macro_rules! define_lands {
         /// the kind.
         #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
         #[allow(missing_docs)]
-        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
         pub enum LandKind {
             $($kind,)+
         }
 
         impl LandKind {
+            /// All land kinds in order of their definitions.
+            pub const ALL: &'static [LandKind] = &[$(LandKind::$kind,)+];
+
             /// Creates a preset constant with given altitude.
             #[inline]
-            pub fn preset(self, altitude: u8) -> Land<'static> {
+            pub fn preset(self, altitude: u8) -> Land {
                 match self {
                     $(
                         LandKind::$kind => { let mut l = $name.clone(); l.altitude = altitude; l },
@@ -97,7 +103,7 @@ macro_rules! define_lands {
 
             /// Creates a reference to preset constant
             #[inline]
-            pub fn preset_ref(self) -> &'static Land<'static> {
+            pub fn preset_ref(self) -> &'static Land {
                 match self {
                     $(
                         LandKind::$kind => &$name,
@@ -118,9 +124,9 @@ macro_rules! define_lands {
 
         lazy_static! {
             $(
-                static ref $name: Land<'static> = Land {
+                static ref $name: Land = Land {
                     kind: LandKind::$kind,
-                    char: "██",
+                    char: Cow::Borrowed("██"),
                     color: {
                         let mut c = ColorSpec::new();
                         c.set_fg(Some(Color::Ansi256($color)));
@@ -143,6 +149,28 @@ define_lands! {
     ALPINE   = (Highland, 58, "Highland");
     DEEPSEA  = (DeepSea,  63, "Deep Sea");
     PATH     = (Path,    193, "Path");
+    RIVER    = (River,    38, "River");
+    LAKE     = (Lake,     74, "Lake");
+    DESERT   = (Desert,  222, "Desert");
+    TUNDRA   = (Tundra,  145, "Tundra");
+    SNOW     = (Snow,    255, "Snow");
+    SWAMP    = (Swamp,    65, "Swamp");
+    JUNGLE   = (Jungle,   28, "Jungle");
+    SAVANNA  = (Savanna, 186, "Savanna");
+}
+
+impl LandKind {
+    /// Returns whether the land kind is water such as sea.
+    #[inline]
+    pub fn is_water(self) -> bool {
+        matches!(self, LandKind::Sea | LandKind::DeepSea)
+    }
+
+    /// Returns whether the land kind is inland water such as lake or river.
+    #[inline]
+    pub fn is_inland_water(self) -> bool {
+        matches!(self, LandKind::Lake | LandKind::River)
+    }
 }
 
 #[cfg(test)]
@@ -151,9 +179,10 @@ mod tests {
     use std::collections::HashSet;
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn constants() {
         let mut saw = HashSet::new();
-        for kind in &[
+        let kinds = [
             LandKind::Sea,
             LandKind::Mountain,
             LandKind::Forest,
@@ -163,7 +192,17 @@ mod tests {
             LandKind::Highland,
             LandKind::DeepSea,
             LandKind::Path,
-        ] {
+            LandKind::River,
+            LandKind::Lake,
+            LandKind::Desert,
+            LandKind::Tundra,
+            LandKind::Snow,
+            LandKind::Swamp,
+            LandKind::Jungle,
+            LandKind::Savanna,
+        ];
+        assert_eq!(LandKind::ALL, &kinds);
+        for kind in kinds.iter() {
             let land = kind.preset(0);
             assert_eq!(&land.kind, kind);
             match land.color.fg() {
@@ -2,12 +2,17 @@ use rand;
 
 use self::rand::seq::SliceRandom;
 use self::rand::Rng;
-use crate::board::{Board, Pos};
+use crate::biome::{sea_level_for_water_ratio, BiomeTable};
+use crate::board::{Board, Pos, Wrap};
+use crate::climate::Climate;
+use crate::gen::{GenConfig, Resolution};
 use crate::land::LandKind;
-use crate::slope::SlopeGen;
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::rc::Rc;
+use crate::path::{kind_cost, Grid, Search, MIN_LAND_COST};
+use crate::river::{lakes, rivers};
+use crate::terrain::{highest_peaks, Heightmap};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::slice;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 struct Connection<'a> {
@@ -21,9 +26,16 @@ pub struct LargeBoardGen<'a, R: Rng> {
     height: usize,
     max_towns: usize,
     num_tops: usize,
+    num_rivers: usize,
+    lake_min_area: usize,
     town_min_cost: usize,
     conn_max_cost: usize,
     down_rate: u8,
+    biomes: BiomeTable,
+    rivers: HashSet<Pos>,
+    lakes: HashSet<Pos>,
+    climate: Option<Climate>,
+    config: &'a GenConfig,
 }
 
 // This warning is raised only on wasm32 target since Pos contains usize fields.
@@ -31,13 +43,19 @@ pub struct LargeBoardGen<'a, R: Rng> {
 // So using `self` argument is efficient on wasm32, but not on x86_64.
 #[allow(clippy::trivially_copy_pass_by_ref)]
 impl<'a, R: Rng> LargeBoardGen<'a, R> {
-    pub fn new<'b: 'a>(rng: &'b mut R, width: usize, height: usize) -> Self {
-        let max_towns = rng.gen_range(10, 16);
-        let num_tops = width * height / 2048 + rng.gen_range(0, 4);
+    pub fn new<'b: 'a>(rng: &'b mut R, width: usize, height: usize, config: &'b GenConfig) -> Self {
+        let max_towns = config.max_towns.unwrap_or_else(|| rng.gen_range(10, 16));
+        let num_tops = config
+            .num_tops
+            .unwrap_or_else(|| width * height / 2048 + rng.gen_range(0, 4));
+        let num_rivers = config.num_rivers.unwrap_or(num_tops);
+        let lake_min_area = config.lake_min_area.unwrap_or(16);
         let average_len = (width + height) / 2;
-        let town_min_cost = average_len.checked_div(max_towns).unwrap_or(average_len);
-        let conn_max_cost = average_len / 2;
-        let down_rate = 6; // Set smaller down rate for larger map
+        let town_min_cost = config
+            .town_min_cost
+            .unwrap_or_else(|| average_len.checked_div(max_towns).unwrap_or(average_len));
+        let conn_max_cost = config.conn_max_cost.unwrap_or(average_len / 2);
+        let down_rate = config.down_rate.unwrap_or(6); // Set smaller down rate for larger map
 
         LargeBoardGen {
             rng,
@@ -45,22 +63,32 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
             width,
             max_towns,
             num_tops,
+            num_rivers,
+            lake_min_area,
             town_min_cost,
             conn_max_cost,
             down_rate,
+            biomes: config.biome_table(Resolution::High),
+            rivers: HashSet::new(),
+            lakes: HashSet::new(),
+            climate: None,
+            config,
         }
     }
 
     #[inline]
-    fn land_kind(altitude: u8) -> LandKind {
-        match altitude {
-            0..=40 => LandKind::DeepSea,
-            41..=55 => LandKind::Sea,
-            56..=70 => LandKind::Plain,
-            71..=80 => LandKind::Forest,
-            81..=90 => LandKind::Mountain,
-            91..=99 => LandKind::Highland,
-            _ => unreachable!(),
+    fn land_kind(&self, altitudes: &[Vec<u8>], x: usize, y: usize) -> LandKind {
+        let p = Pos { x, y };
+        if self.rivers.contains(&p) {
+            LandKind::River
+        } else if self.lakes.contains(&p) {
+            LandKind::Lake
+        } else {
+            let kind = self.biomes.land_kind(altitudes[y][x]);
+            match &self.climate {
+                Some(climate) => climate.classify(kind, x, y),
+                None => kind,
+            }
         }
     }
 
@@ -70,12 +98,12 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
         fn land_fitness(kind: LandKind) -> u8 {
             match kind {
                 LandKind::DeepSea => 0,
-                LandKind::Sea => 16,
-                LandKind::Plain => 8,
-                LandKind::Forest => 4,
-                LandKind::Mountain => 2,
-                LandKind::Highland => 1,
-                _ => unreachable!(),
+                LandKind::Sea | LandKind::River | LandKind::Lake => 16,
+                LandKind::Plain | LandKind::Savanna => 8,
+                LandKind::Forest | LandKind::Jungle => 4,
+                LandKind::Mountain | LandKind::Desert | LandKind::Tundra | LandKind::Swamp => 2,
+                LandKind::Highland | LandKind::Top | LandKind::Snow => 1,
+                LandKind::Town | LandKind::Path => 8,
             }
         }
 
@@ -84,43 +112,58 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
         for y in 0..self.height {
             let mut row = Vec::with_capacity(self.width);
             for x in 0..self.width {
-                row.push(land_fitness(Self::land_kind(altitudes[y][x])))
+                row.push(land_fitness(self.land_kind(altitudes, x, y)))
             }
             fitness.push(row);
         }
 
-        // Cells at edges of map, (0, y), (x, 0), (MAX, y), (x, MAX), never become towns
+        // Cells at edges of map, (0, y), (x, 0), (MAX, y), (x, MAX), never become towns. Edges
+        // connected to the opposite edges are not regarded as edges on wrapped board.
 
-        fn convo_3times(fitness: &mut Vec<Vec<u8>>) {
+        fn convo_3times(fitness: &mut [Vec<u8>], wrap: Wrap) {
+            let height = fitness.len();
+            let width = fitness.first().map(Vec::len).unwrap_or(0);
             for _ in 0..3 {
-                for y in 1..fitness.len() - 1 {
-                    for x in 1..fitness[y].len() - 1 {
-                        let mut sum = 0 as i32;
-                        for y in &[y - 1, y, y + 1] {
-                            for x in &[x - 1, x, x + 1] {
-                                sum += i32::from(fitness[*y][*x]);
+                for y in 0..height {
+                    for x in 0..width {
+                        let mut sum = 0i32;
+                        let mut inside = true;
+                        for dy in -1..=1 {
+                            for dx in -1..=1 {
+                                match wrap.offset(&Pos { x, y }, dx, dy, width, height) {
+                                    Some(p) => sum += i32::from(fitness[p.y][p.x]),
+                                    None => inside = false,
+                                }
                             }
                         }
-                        fitness[y][x] = (sum / 9) as u8;
+                        if inside {
+                            fitness[y][x] = (sum / 9) as u8;
+                        }
                     }
                 }
             }
         }
 
-        convo_3times(&mut fitness);
+        let wrap = self.config.wrap;
+        let is_edge = |x: usize, y: usize| {
+            (!wrap.vertical() && (y == 0 || y == self.height - 1))
+                || (!wrap.horizontal() && (x == 0 || x == self.width - 1))
+        };
+
+        convo_3times(&mut fitness, wrap);
         for y in 0..self.height {
             for x in 0..self.width {
-                if y == 0
-                    || x == 0
-                    || y == self.height - 1
-                    || x == self.width - 1
-                    || Self::land_kind(altitudes[y][x]) != LandKind::Plain
+                if is_edge(x, y)
+                    || !matches!(
+                        self.land_kind(altitudes, x, y),
+                        LandKind::Plain | LandKind::Savanna
+                    )
                 {
                     fitness[y][x] = 0;
                 }
             }
         }
-        convo_3times(&mut fitness);
+        convo_3times(&mut fitness, wrap);
 
         let mut min_fitness = 0;
         for row in fitness.iter() {
@@ -130,12 +173,16 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
                 }
             }
         }
+        if min_fitness == 0 {
+            // No plain to put a town
+            return HashSet::new();
+        }
         let min_fitness = min_fitness * 9 / 10; // * 0.9
 
         let mut candidates = Vec::new();
-        for y in 1..fitness.len() - 1 {
-            for x in 1..fitness[y].len() - 1 {
-                if fitness[y][x] >= min_fitness {
+        for (y, row) in fitness.iter().enumerate() {
+            for (x, f) in row.iter().enumerate() {
+                if *f >= min_fitness && !is_edge(x, y) {
                     candidates.push(Pos { x, y });
                 }
             }
@@ -147,143 +194,72 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
             if towns.len() == self.max_towns {
                 break;
             }
-            if towns
-                .iter()
-                .all(|p: &Pos| p.move_cost(c) > self.town_min_cost)
-            {
+            if towns.iter().all(|p: &Pos| {
+                let (layout, neighborhood) = (self.config.layout, self.config.neighborhood);
+                let cost = layout.move_cost(p, c, wrap, neighborhood, self.width, self.height);
+                cost > self.town_min_cost
+            }) {
                 towns.insert(*c);
             }
         }
         towns
     }
 
-    // Get shortest path of the connection using Dijkstra's algorithm
-    fn shortest_path<'b>(&self, conn: &Connection<'b>, altitudes: &[Vec<u8>]) -> Vec<Pos> {
-        #[inline]
-        fn land_cost(kind: LandKind) -> usize {
-            match kind {
-                LandKind::DeepSea => 64,
-                LandKind::Sea => 32,
-                LandKind::Plain => 1,
-                LandKind::Forest => 4,
-                LandKind::Mountain => 8,
-                LandKind::Highland => 16,
-                _ => unreachable!(),
-            }
-        }
-
-        #[derive(Clone)]
-        enum Route {
-            Cons(Pos, Rc<Route>),
-            Nil,
-        }
-
-        struct Vert {
-            cost: usize,
-            pos: Pos,
-            prev: Route,
-        }
-
-        // Note: Vert is ordered by cost for priority queue
-
-        impl PartialEq for Vert {
-            fn eq(&self, rhs: &Vert) -> bool {
-                self.cost == rhs.cost
-            }
-        }
-        impl Eq for Vert {}
-
-        impl Ord for Vert {
-            fn cmp(&self, rhs: &Vert) -> Ordering {
-                rhs.cost.cmp(&self.cost)
-            }
-        }
-
-        impl PartialOrd for Vert {
-            fn partial_cmp(&self, rhs: &Vert) -> Option<Ordering> {
-                Some(self.cmp(rhs))
-            }
-        }
-
-        // Map node => cost
-        let mut costs = HashMap::new();
-        costs.insert(*conn.from, 0);
-
-        let mut state = BinaryHeap::new();
-        state.push(Vert {
-            cost: 0,
-            pos: *conn.from,
-            prev: Route::Nil,
-        });
-
-        while let Some(Vert { cost, pos, prev }) = state.pop() {
-            if &pos == conn.to {
-                // Collect list as Vec<Pos>
-                // Note: Start node and goal node are not included since they are town
-                let mut verts = Vec::new();
-                let mut route = &prev;
-                while let Route::Cons(pos, ref prev) = route {
-                    verts.push(*pos);
-                    route = prev;
-                }
-                return verts;
-            }
-
-            // Note: OK to create an Rc pointer in advance since at least one point iterated in below loop is valid.
-            let prev = Rc::new(prev);
-            let Pos { x, y } = pos;
-            for pair in &[
-                (Some(x), y.checked_sub(1)),
-                (x.checked_sub(1), Some(y)),
-                (x.checked_add(1), Some(y)),
-                (Some(x), y.checked_add(1)),
-            ] {
-                let (x, y) = match pair {
-                    (Some(x), Some(y)) if *x < self.width && *y < self.height => (*x, *y),
-                    _ => continue,
-                };
-
-                if let Route::Cons(pos, ..) = *prev {
-                    if pos.x == x && pos.y == y {
-                        // Going back to previous position never happens
-                        continue;
-                    }
-                }
-
-                let cost = cost + land_cost(Self::land_kind(altitudes[y][x]));
-                let pos = Pos { x, y };
-
-                if let Some(c) = costs.get(&pos) {
-                    if cost >= *c {
-                        continue;
-                    }
-                }
-
-                costs.insert(pos, cost);
-                state.push(Vert {
-                    cost,
-                    pos,
-                    prev: Route::Cons(pos, prev.clone()),
-                });
-            }
+    // Get shortest path of the connection. Water is passable with high cost to build bridges but
+    // diagonal steps never cut a corner of water. Note that the goal is included in the path but
+    // the start is not.
+    fn shortest_path(
+        &self,
+        conn: &Connection<'_>,
+        altitudes: &[Vec<u8>],
+        search: &mut Search,
+    ) -> Vec<Pos> {
+        let kind = |p: &Pos| self.land_kind(altitudes, p.x, p.y);
+        let goal = search.run(
+            conn.from,
+            slice::from_ref(conn.to),
+            MIN_LAND_COST,
+            usize::MAX,
+            |p| Some(kind_cost(kind(p))),
+            |p| {
+                let k = kind(p);
+                k.is_water() || k.is_inland_water()
+            },
+        );
+        match goal {
+            Some(goal) => search.route(conn.from, &goal).split_off(1),
+            None => Vec::new(), // Connection unreachable
         }
-
-        // Connection unreachable
-        Vec::new()
     }
 
     // Get all cells of paths
     fn paths(&mut self, towns: &HashSet<Pos>, altitudes: &[Vec<u8>]) -> HashSet<Pos> {
+        // Towns are sorted since connections depend on the order. Iterating a hash set would make
+        // different paths from the same seed.
+        let mut towns = towns.iter().collect::<Vec<_>>();
+        towns.sort_by_key(|p| (p.y, p.x));
+        let towns = &towns;
+        let (wrap, layout) = (self.config.wrap, self.config.layout);
+        let neighborhood = self.config.neighborhood;
+        let (width, height) = (self.width, self.height);
+        let mut search = Search::new(Grid {
+            width,
+            height,
+            layout,
+            wrap,
+            neighborhood,
+        });
+
         towns
             .iter()
-            .map(|town| {
+            .flat_map(|town| {
                 let mut near_towns = towns
                     .iter()
                     .filter_map(|t| {
                         if t == town {
                             return None;
                         }
-                        let cost = t.move_cost(town);
+                        let cost = layout.move_cost(t, town, wrap, neighborhood, width, height);
                         if cost > self.conn_max_cost {
                             return None;
                         }
@@ -299,8 +275,10 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
                     // f64::from instead is safer. This is true on wasm32, but on x86_64, conversion
                     // between usize and f64 is not lossless so f64::from(usize) is not implemented.
                     #[allow(clippy::cast_lossless)]
-                    let angle =
-                        (town.y as f64 - near.y as f64).atan2(town.x as f64 - near.x as f64);
+                    let angle = {
+                        let (dx, dy) = wrap.delta(near, town, width, height);
+                        (dy as f64).atan2(dx as f64)
+                    };
                     let dir = (angle / 45.0) as usize;
                     for dir in &[dir, (dir + 1) % 8, (dir + 7) % 8] {
                         if dirs.contains(dir) {
@@ -310,12 +288,11 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
 
                     dirs.insert(dir);
                     Some(Connection {
-                        from: &town,
-                        to: &near,
+                        from: town,
+                        to: near,
                     })
                 })
             })
-            .flatten()
             .filter({
                 // Dedup connections (from-to pairs)
                 let mut saw = HashMap::new();
@@ -334,27 +311,72 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
                     true
                 }
             })
-            .map(|conn| self.shortest_path(&conn, altitudes))
-            .flatten()
+            .flat_map(|conn| self.shortest_path(&conn, altitudes, &mut search))
             .collect()
     }
 
-    pub fn gen(&mut self) -> Board<'static> {
-        let mut slope = SlopeGen::new(
+    pub fn gen(&mut self) -> Board {
+        let heightmap = self.config.terrain.gen(
             self.rng,
             self.width,
             self.height,
             self.down_rate,
             self.num_tops,
+            self.config.wrap,
+            self.config.layout,
+        );
+        self.gen_on(heightmap)
+    }
+
+    // Generate a board from the given altitudes. Tops of mountains are chosen from the highest
+    // local maxima.
+    pub fn gen_from_altitudes(&mut self, altitudes: Vec<Vec<u8>>) -> Board {
+        let tops = highest_peaks(&altitudes, self.num_tops, self.config.wrap);
+        self.gen_on(Heightmap { altitudes, tops })
+    }
+
+    fn gen_on(&mut self, heightmap: Heightmap) -> Board {
+        let Heightmap {
+            mut altitudes,
+            tops,
+        } = heightmap;
+
+        if let Some(erosion) = &self.config.erosion {
+            erosion.erode(self.rng, &mut altitudes, self.config.wrap);
+        }
+
+        if let Some(ratio) = self.config.water_ratio {
+            let level = sea_level_for_water_ratio(altitudes.iter().flatten().cloned(), ratio);
+            self.biomes = self.biomes.with_sea_level(level);
+        }
+
+        let biomes = &self.biomes;
+        let is_water = |alt| biomes.land_kind(alt).is_water();
+        let (wrap, layout) = (self.config.wrap, self.config.layout);
+        self.lakes = lakes(&altitudes, is_water, self.lake_min_area, wrap, layout);
+
+        // Rivers flow from higher tops. Tops are sorted for deterministic result.
+        let mut sources = tops.iter().cloned().collect::<Vec<_>>();
+        sources.sort_by_key(|p| (Reverse(altitudes[p.y][p.x]), p.y, p.x));
+        sources.truncate(self.num_rivers);
+        self.rivers = rivers(
+            &mut altitudes,
+            &sources,
+            &self.lakes,
+            is_water,
+            wrap,
+            layout,
         );
-        slope.gen();
-        let altitudes = slope.altitudes;
-        let tops = slope.tops;
+
+        if let Some(params) = &self.config.climate {
+            let sea_level = self.biomes.sea_level();
+            self.climate = Some(Climate::new(params, &altitudes, sea_level, wrap));
+        }
 
         let towns = self.towns(&altitudes);
         let paths = self.paths(&towns, &altitudes);
 
-        Board::build(self.width, self.height, |w, h| {
+        let mut board = Board::build(self.width, self.height, |w, h| {
             let alt = altitudes[h][w];
             let p = Pos { x: w, y: h };
             if tops.contains(&p) {
@@ -362,10 +384,72 @@ impl<'a, R: Rng> LargeBoardGen<'a, R> {
             } else if towns.contains(&p) {
                 LandKind::Town.preset(alt)
             } else if paths.contains(&p) {
+                // Paths crossing rivers are bridges
                 LandKind::Path.preset(alt)
             } else {
-                Self::land_kind(alt).preset(alt)
+                self.land_kind(&altitudes, w, h).preset(alt)
             }
-        })
+        });
+        board.set_layout(self.config.layout);
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Neighborhood;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn path(config: &GenConfig, altitudes: &[Vec<u8>], from: Pos, to: Pos) -> Vec<Pos> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (w, h) = (altitudes[0].len(), altitudes.len());
+        let gen = LargeBoardGen::new(&mut rng, w, h, config);
+        let mut search = Search::new(Grid {
+            width: w,
+            height: h,
+            layout: config.layout,
+            wrap: config.wrap,
+            neighborhood: config.neighborhood,
+        });
+        let conn = Connection {
+            from: &from,
+            to: &to,
+        };
+        let mut path = gen.shortest_path(&conn, altitudes, &mut search);
+        path.sort_by_key(|p| (p.y, p.x));
+        path
+    }
+
+    #[test]
+    fn diagonal_path() {
+        let plain = vec![vec![60; 5]; 5];
+        let (from, to) = (Pos { x: 0, y: 0 }, Pos { x: 4, y: 4 });
+
+        let config = GenConfig::default();
+        // Note: Goal is included in the path
+        assert_eq!(path(&config, &plain, from, to).len(), 8);
+
+        let config = GenConfig {
+            neighborhood: Neighborhood::Eight,
+            ..GenConfig::default()
+        };
+        let expected = (1..5).map(|i| Pos { x: i, y: i }).collect::<Vec<_>>();
+        assert_eq!(path(&config, &plain, from, to), expected);
+    }
+
+    #[test]
+    fn diagonal_path_never_cuts_corner_of_water() {
+        // Diagonal line of sea separates the board
+        let altitudes = (0..5)
+            .map(|y| (0..5).map(|x| if x == y { 0 } else { 60 }).collect())
+            .collect::<Vec<Vec<u8>>>();
+        let config = GenConfig {
+            neighborhood: Neighborhood::Eight,
+            ..GenConfig::default()
+        };
+        let path = path(&config, &altitudes, Pos { x: 4, y: 0 }, Pos { x: 0, y: 4 });
+        assert!(path.iter().any(|p| p.x == p.y), "{:?}", path);
     }
 }
@@ -15,9 +15,15 @@
 //! This library provides some modules to handle a world map as one board filled up with cells.
 //!
 //! - `land`: `land::Land` struct represents each cell in a board
-//! - `board`: `board::Board` struct represents one world map. The struct is JSON serializable with `serde_json`
-//! - `draw`: Helper to draw a board to terminal or as JSON
+//! - `board`: `board::Board` struct represents one world map. The struct is JSON serializable and deserializable with `serde_json`
+//! - `draw`: Helper to draw a board to terminal, as JSON, as image (PNG, SVG), as Tiled map or as 3D mesh (OBJ, glTF)
+//! - `binary`: Compact binary format of a board with reader and writer
+//! - `heightmap`: Export and import of altitudes of a board as grayscale heightmap image
 //! - `gen`: A random world map generator to build `board::Board` struct. It provides algorithms for 3 kinds of resolutions
+//! - `terrain`: Algorithms to generate altitudes of cells. It is selectable via `gen::GenConfig`
+//! - `biome`: A table to classify cells into land kinds by their altitudes. It is customizable via `gen::GenConfig`
+//! - `climate`: A climate model to classify cells into biomes by temperature and moisture. It is enabled via `gen::GenConfig`
+//! - `path`: Path finding on a board with customizable costs of lands
 //! - `error`: Error type which may be returned from a map generator
 //!
 //! ```rust
@@ -56,18 +62,30 @@ extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod binary;
+pub mod biome;
 pub mod board;
+pub mod climate;
 pub mod draw;
 pub mod error;
 pub mod gen;
+pub mod heightmap;
 pub mod land;
+pub mod path;
+pub mod terrain;
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
 mod color;
+mod diamond_square;
+mod erosion;
 mod large_gen;
+mod mesh;
 mod middle_gen;
+mod noise;
+mod river;
 mod slope;
+mod tiled;
 
 pub use crate::board::Board;
 pub use crate::error::Result;
@@ -1,10 +1,16 @@
 use clap::{App, Arg};
-use std::{fmt, io};
-use world_map_gen::{draw, gen};
+use std::path::Path;
+use std::{fmt, fs, io};
+use world_map_gen::binary;
+use world_map_gen::board::{Board, Layout, Neighborhood, Wrap};
+use world_map_gen::{draw, gen, heightmap, terrain};
 
+#[allow(clippy::enum_variant_names)]
 enum Error {
     GenFail(world_map_gen::error::Error),
     CliParseFail { name: String, msg: String },
+    ConfigLoadFail { path: String, msg: String },
+    BoardLoadFail { path: String, msg: String },
 }
 
 impl fmt::Debug for Error {
@@ -14,6 +20,12 @@ impl fmt::Debug for Error {
             Error::CliParseFail { name, msg } => {
                 write!(f, "Cannot parse CLI option '{}': {}", name, msg)
             }
+            Error::ConfigLoadFail { path, msg } => {
+                write!(f, "Cannot load config file '{}': {}", path, msg)
+            }
+            Error::BoardLoadFail { path, msg } => {
+                write!(f, "Cannot load map file '{}': {}", path, msg)
+            }
         }
     }
 }
@@ -41,6 +53,42 @@ where
     }
 }
 
+fn load_config(path: &str) -> Result<gen::GenConfig, Error> {
+    let fail = |msg: String| Error::ConfigLoadFail {
+        path: path.to_string(),
+        msg,
+    };
+    let content = fs::read_to_string(path).map_err(|e| fail(format!("{}", e)))?;
+    serde_json::from_str(&content).map_err(|e| fail(format!("{}", e)))
+}
+
+fn load_board(path: &str) -> Result<Board, Error> {
+    let fail = |msg: String| Error::BoardLoadFail {
+        path: path.to_string(),
+        msg,
+    };
+    let content = fs::read_to_string(path).map_err(|e| fail(format!("{}", e)))?;
+    serde_json::from_str(&content).map_err(|e| fail(format!("{}", e)))
+}
+
+// Size of tiles in pixels in Tiled map
+const TILE_SIZE: usize = 16;
+
+// Options to output a map. Only one of them can be specified at once.
+const OUTPUTS: &[&str] = &[
+    "json",
+    "png",
+    "svg",
+    "binary",
+    "tiled",
+    "mesh",
+    "export-heightmap",
+];
+
+fn other_outputs(name: &str) -> Vec<&'static str> {
+    OUTPUTS.iter().cloned().filter(|o| *o != name).collect()
+}
+
 fn main() -> Result<(), Error> {
     let matches = App::new("world-map-gen")
         .version("0.1")
@@ -75,6 +123,90 @@ fn main() -> Result<(), Error> {
                 .possible_values(&["low", "middle", "high"])
                 .help("Resolution of world map"),
         )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("JSON file of parameters for generating middle and high resolution maps"),
+        )
+        .arg(
+            Arg::with_name("terrain")
+                .short("t")
+                .long("terrain")
+                .value_name("STRING")
+                .possible_values(&["slope", "noise", "diamond-square"])
+                .help("Algorithm to generate altitudes of middle and high resolution maps"),
+        )
+        .arg(
+            Arg::with_name("wrap")
+                .long("wrap")
+                .value_name("STRING")
+                .possible_values(&["none", "horizontal", "both"])
+                .help("Connect edges of middle and high resolution maps to make them seamless"),
+        )
+        .arg(
+            Arg::with_name("layout")
+                .long("layout")
+                .value_name("STRING")
+                .possible_values(&["square", "hex"])
+                .help("Layout of cells of map. Odd rows are staggered on terminal with 'hex'"),
+        )
+        .arg(
+            Arg::with_name("diagonal")
+                .long("diagonal")
+                .help("Allow paths of high resolution maps to go diagonally in 8 directions"),
+        )
+        .arg(
+            Arg::with_name("sea-level")
+                .long("sea-level")
+                .value_name("INTEGER")
+                .help(
+                    "Sea level as altitude in 0..=99. Cells lower than or equal to it become water",
+                ),
+        )
+        .arg(
+            Arg::with_name("water-ratio")
+                .long("water-ratio")
+                .value_name("FLOAT")
+                .conflicts_with("sea-level")
+                .help(
+                    "Ratio of water cells in 0.0..=1.0. Sea level is determined to match the ratio",
+                ),
+        )
+        .arg(
+            Arg::with_name("erosion")
+                .short("e")
+                .long("erosion")
+                .help("Erode terrain of middle and high resolution maps with default parameters"),
+        )
+        .arg(
+            Arg::with_name("climate")
+                .long("climate")
+                .help("Classify land of middle and high resolution maps into biomes by climate"),
+        )
+        .arg(
+            Arg::with_name("heightmap")
+                .long("heightmap")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Generate map on altitudes of the PGM or PNG heightmap image instead of random terrain"),
+        )
+        .arg(
+            Arg::with_name("load")
+                .long("load")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Load map from the file in compact binary format instead of generating it"),
+        )
+        .arg(
+            Arg::with_name("regenerate")
+                .long("regenerate")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["load", "heightmap"])
+                .help("Generate the same map again from metadata in the JSON file output by --json"),
+        )
         .arg(
             Arg::with_name("altitude")
                 .short("a")
@@ -85,13 +217,85 @@ fn main() -> Result<(), Error> {
             Arg::with_name("json")
                 .short("j")
                 .long("json")
+                .conflicts_with_all(&other_outputs("json"))
                 .help("Output JSON-serialized result to stdout"),
         )
+        .arg(
+            Arg::with_name("compact")
+                .long("compact")
+                .requires("json")
+                .help("Output JSON with palette of land kinds and flat arrays of cells with --json"),
+        )
+        .arg(
+            Arg::with_name("png")
+                .long("png")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("png"))
+                .help("Write map as PNG image to the file instead of rendering it to terminal"),
+        )
+        .arg(
+            Arg::with_name("svg")
+                .long("svg")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("svg"))
+                .help("Write map as SVG image to the file instead of rendering it to terminal"),
+        )
+        .arg(
+            Arg::with_name("binary")
+                .long("binary")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("binary"))
+                .help("Write map to the file in compact binary format compressed with deflate"),
+        )
+        .arg(
+            Arg::with_name("tiled")
+                .long("tiled")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("tiled"))
+                .help("Write map as JSON map of Tiled map editor to the file. Its tileset image is written to '{FILE stem}-tileset.png' in the same directory"),
+        )
+        .arg(
+            Arg::with_name("mesh")
+                .long("mesh")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("mesh"))
+                .help("Write altitudes of map as 3D mesh to the file. Its format is Wavefront OBJ for '.obj' extension and binary glTF otherwise"),
+        )
+        .arg(
+            Arg::with_name("exaggeration")
+                .long("exaggeration")
+                .takes_value(true)
+                .value_name("FLOAT")
+                .help("Vertical exaggeration of altitudes in 3D mesh (default: 1.0)"),
+        )
+        .arg(
+            Arg::with_name("export-heightmap")
+                .long("export-heightmap")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&other_outputs("export-heightmap"))
+                .help("Write altitudes of map as grayscale heightmap image to the file. Its format is PGM for '.pgm' extension and 16-bit PNG otherwise"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .takes_value(true)
+                .value_name("INTEGER")
+                .help("Number of pixels per cell side in output image (default: 4)"),
+        )
         .get_matches();
 
-    let seed = parse_opt("seed", matches.value_of("seed"))?;
+    // Seed is always determined so that a generated map can be reproduced from its metadata
+    let seed = parse_opt("seed", matches.value_of("seed"))?.unwrap_or_else(rand::random);
     let width = parse_opt("width", matches.value_of("width"))?;
     let height = parse_opt("height", matches.value_of("height"))?;
+    let scale = parse_opt("scale", matches.value_of("scale"))?.unwrap_or(4);
+    let exaggeration = parse_opt("exaggeration", matches.value_of("exaggeration"))?.unwrap_or(1.0);
     let resolution = matches.value_of("resolution").map(|s| match s {
         "low" => gen::Resolution::Low,
         "middle" => gen::Resolution::Middle,
@@ -99,14 +303,133 @@ fn main() -> Result<(), Error> {
         _ => unreachable!(),
     });
 
-    let board = if let Some(seed) = seed {
-        gen::RandomBoardGen::from_seed(seed).gen(resolution, width, height)?
+    let mut config = match matches.value_of("config") {
+        Some(path) => load_config(path)?,
+        None => gen::GenConfig::default(),
+    };
+    match matches.value_of("terrain") {
+        Some("slope") => config.terrain = terrain::Terrain::Slope,
+        Some("noise") => config.terrain = terrain::Terrain::Noise(Default::default()),
+        Some("diamond-square") => {
+            config.terrain = terrain::Terrain::DiamondSquare(Default::default())
+        }
+        _ => {}
+    }
+    match matches.value_of("wrap") {
+        Some("none") => config.wrap = Wrap::None,
+        Some("horizontal") => config.wrap = Wrap::Horizontal,
+        Some("both") => config.wrap = Wrap::Both,
+        _ => {}
+    }
+    match matches.value_of("layout") {
+        Some("square") => config.layout = Layout::Square,
+        Some("hex") => config.layout = Layout::Hex,
+        _ => {}
+    }
+    if matches.is_present("diagonal") {
+        config.neighborhood = Neighborhood::Eight;
+    }
+    if matches.is_present("erosion") && config.erosion.is_none() {
+        config.erosion = Some(Default::default());
+    }
+    if matches.is_present("climate") && config.climate.is_none() {
+        config.climate = Some(Default::default());
+    }
+    if let Some(level) = parse_opt("sea-level", matches.value_of("sea-level"))? {
+        config.sea_level = Some(level);
+        config.water_ratio = None;
+    }
+    if let Some(ratio) = parse_opt("water-ratio", matches.value_of("water-ratio"))? {
+        config.water_ratio = Some(ratio);
+    }
+
+    // Loaded board has no metadata. Its seed and resolution are kept in the header
+    let mut loaded_header = None;
+    let board = if let Some(path) = matches.value_of("load") {
+        let file = fs::File::open(path).map_err(world_map_gen::error::Error::from)?;
+        let (board, header) = Board::read_binary(io::BufReader::new(file))?;
+        loaded_header = Some(header);
+        board
+    } else if let Some(path) = matches.value_of("regenerate") {
+        let board = load_board(path)?;
+        if let Some(version) = board.metadata().map(|m| &m.version) {
+            if version != env!("CARGO_PKG_VERSION") {
+                eprintln!(
+                    "Warning: Map was generated by version {} but this is version {}. Generated map may be different",
+                    version,
+                    env!("CARGO_PKG_VERSION"),
+                );
+            }
+        }
+        gen::RandomBoardGen::regenerate(&board)?
+    } else if let Some(path) = matches.value_of("heightmap") {
+        let file = fs::File::open(path).map_err(world_map_gen::error::Error::from)?;
+        let altitudes = heightmap::read(io::BufReader::new(file))?;
+        gen::RandomBoardGen::from_seed(seed)
+            .with_config(config)
+            .gen_from_heightmap(resolution, altitudes)?
     } else {
-        gen::RandomBoardGen::default().gen(resolution, width, height)?
+        gen::RandomBoardGen::from_seed(seed)
+            .with_config(config)
+            .gen(resolution, width, height)?
     };
 
-    if matches.is_present("json") {
-        draw::draw_json(&mut io::stdout(), &board)?;
+    if let Some(path) = matches.value_of("png") {
+        let file =
+            io::BufWriter::new(fs::File::create(path).map_err(world_map_gen::error::Error::from)?);
+        draw::draw_png(file, &board, scale)?;
+    } else if let Some(path) = matches.value_of("svg") {
+        let mut file =
+            io::BufWriter::new(fs::File::create(path).map_err(world_map_gen::error::Error::from)?);
+        draw::draw_svg(&mut file, &board)?;
+    } else if let Some(path) = matches.value_of("tiled") {
+        let path = Path::new(path);
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("map");
+        let tileset = format!("{}-tileset.png", stem);
+        let create = |p: &Path| {
+            fs::File::create(p)
+                .map(io::BufWriter::new)
+                .map_err(world_map_gen::error::Error::from)
+        };
+        draw::draw_tiled(&mut create(path)?, &board, &tileset, TILE_SIZE)?;
+        draw::draw_tiled_tileset(create(&path.with_file_name(&tileset))?, TILE_SIZE)?;
+    } else if let Some(path) = matches.value_of("mesh") {
+        let mut file =
+            io::BufWriter::new(fs::File::create(path).map_err(world_map_gen::error::Error::from)?);
+        if path.ends_with(".obj") {
+            draw::draw_obj(&mut file, &board, exaggeration)?;
+        } else {
+            draw::draw_glb(&mut file, &board, exaggeration)?;
+        }
+    } else if let Some(path) = matches.value_of("binary") {
+        let file =
+            io::BufWriter::new(fs::File::create(path).map_err(world_map_gen::error::Error::from)?);
+        let header = match loaded_header {
+            Some(header) => binary::Header {
+                compression: Default::default(),
+                ..header
+            },
+            None => binary::Header {
+                resolution: board.metadata().map(|m| m.resolution),
+                seed: board.metadata().and_then(|m| m.seed),
+                ..Default::default()
+            },
+        };
+        board.write_binary(file, &header)?;
+    } else if let Some(path) = matches.value_of("export-heightmap") {
+        let mut file =
+            io::BufWriter::new(fs::File::create(path).map_err(world_map_gen::error::Error::from)?);
+        if path.ends_with(".pgm") {
+            heightmap::write_pgm(&mut file, &board)?;
+        } else {
+            heightmap::write_png(file, &board)?;
+        }
+    } else if matches.is_present("json") {
+        if matches.is_present("compact") {
+            draw::draw_compact_json(&mut io::stdout(), &board)?;
+        } else {
+            draw::draw_json(&mut io::stdout(), &board)?;
+        }
     } else {
         draw::draw_term(&board, matches.is_present("altitude"))?;
     }
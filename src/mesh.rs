@@ -0,0 +1,270 @@
+use crate::board::Board;
+use crate::color::cell_rgb;
+use crate::error::{Error, Result};
+use std::io::Write;
+
+// Number of altitudes per size of a cell
+const ALTITUDES_PER_CELL: f32 = 10.0;
+
+// Magic numbers of binary glTF
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4e4f_534a; // "JSON"
+const CHUNK_BIN: u32 = 0x004e_4942; // "BIN\0"
+
+// Constants of glTF
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const TRIANGLES: u32 = 4;
+
+// Triangle mesh of altitudes. Each cell is a vertex and each 2x2 cells make two triangles. X axis
+// is width, Y axis is altitude and Z axis is height of a board. Colors are in sRGB.
+pub struct Mesh {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(board: &Board, exaggeration: f32) -> Mesh {
+        let (width, height) = (board.width(), board.height());
+        let mut positions = Vec::with_capacity(width * height);
+        let mut colors = Vec::with_capacity(width * height);
+        for (y, row) in board.rows().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let alt = f32::from(cell.altitude) * exaggeration / ALTITUDES_PER_CELL;
+                positions.push([x as f32, alt, y as f32]);
+                let (r, g, b) = cell_rgb(&cell.color);
+                colors.push([
+                    f32::from(r) / 255.0,
+                    f32::from(g) / 255.0,
+                    f32::from(b) / 255.0,
+                ]);
+            }
+        }
+
+        // Triangles are in counter-clockwise order seen from above
+        let mut indices =
+            Vec::with_capacity(width.saturating_sub(1) * height.saturating_sub(1) * 6);
+        for y in 1..height {
+            for x in 1..width {
+                let i = |x: usize, y: usize| (y * width + x) as u32;
+                let (a, b, c, d) = (i(x - 1, y - 1), i(x, y - 1), i(x - 1, y), i(x, y));
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        Mesh {
+            positions,
+            colors,
+            indices,
+        }
+    }
+
+    // Write mesh as Wavefront OBJ with vertex colors extension
+    pub fn write_obj<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "# Generated by world-map-gen")?;
+        for ([x, y, z], [r, g, b]) in self.positions.iter().zip(self.colors.iter()) {
+            writeln!(writer, "v {} {} {} {:.4} {:.4} {:.4}", x, y, z, r, g, b)?;
+        }
+        // Indices of OBJ start from 1
+        for t in self.indices.chunks(3) {
+            writeln!(writer, "f {} {} {}", t[0] + 1, t[1] + 1, t[2] + 1)?;
+        }
+        Ok(())
+    }
+
+    // Write mesh as binary glTF 2.0. Vertex colors are converted into linear color space as glTF
+    // requires. glTF does not allow empty accessors so a mesh without triangles is an error.
+    pub fn write_glb<W: Write>(&self, writer: &mut W) -> Result<()> {
+        if self.indices.is_empty() {
+            return Err(Error::CannotEncodeGltf(
+                "Board must have 2x2 cells at least to build triangles".to_string(),
+            ));
+        }
+
+        fn srgb_to_linear(c: f32) -> f32 {
+            if c <= 0.040_45 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+
+        let mut bin = Vec::new();
+        for p in self.positions.iter() {
+            for v in p.iter() {
+                bin.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        let positions_len = bin.len();
+        for c in self.colors.iter() {
+            for v in c.iter() {
+                bin.extend_from_slice(&srgb_to_linear(*v).to_le_bytes());
+            }
+        }
+        let colors_len = bin.len() - positions_len;
+        for i in self.indices.iter() {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        let indices_len = bin.len() - positions_len - colors_len;
+
+        let (min, max) =
+            self.positions
+                .iter()
+                .fold(([f32::MAX; 3], [f32::MIN; 3]), |(mut min, mut max), p| {
+                    for i in 0..3 {
+                        min[i] = min[i].min(p[i]);
+                        max[i] = max[i].max(p[i]);
+                    }
+                    (min, max)
+                });
+        let count = self.positions.len();
+
+        let json = serde_json::json!({
+            "asset": { "version": "2.0", "generator": "world-map-gen" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "COLOR_0": 1 },
+                    "indices": 2,
+                    "mode": TRIANGLES,
+                }],
+            }],
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": [
+                {
+                    "buffer": 0,
+                    "byteOffset": 0,
+                    "byteLength": positions_len,
+                    "target": ARRAY_BUFFER,
+                },
+                {
+                    "buffer": 0,
+                    "byteOffset": positions_len,
+                    "byteLength": colors_len,
+                    "target": ARRAY_BUFFER,
+                },
+                {
+                    "buffer": 0,
+                    "byteOffset": positions_len + colors_len,
+                    "byteLength": indices_len,
+                    "target": ELEMENT_ARRAY_BUFFER,
+                },
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0,
+                    "componentType": FLOAT,
+                    "count": count,
+                    "type": "VEC3",
+                    "min": min,
+                    "max": max,
+                },
+                { "bufferView": 1, "componentType": FLOAT, "count": count, "type": "VEC3" },
+                {
+                    "bufferView": 2,
+                    "componentType": UNSIGNED_INT,
+                    "count": self.indices.len(),
+                    "type": "SCALAR",
+                },
+            ],
+        });
+        let mut json = serde_json::to_vec(&json)?;
+
+        // Chunks must be aligned to 4 bytes. JSON is padded with spaces and binary with zeros.
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        for v in &[GLB_MAGIC, GLB_VERSION, total as u32] {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        for (ty, data) in &[(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+            writer.write_all(&(data.len() as u32).to_le_bytes())?;
+            writer.write_all(&ty.to_le_bytes())?;
+            writer.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::land::LandKind;
+
+    fn board() -> Board {
+        Board::build(3, 2, |x, y| LandKind::Plain.preset((x + y * 3) as u8 * 10))
+    }
+
+    #[test]
+    fn triangulate_altitudes() {
+        let m = Mesh::new(&board(), 2.0);
+        assert_eq!(m.positions.len(), 6);
+        assert_eq!(m.colors.len(), 6);
+        assert_eq!(m.indices.len(), 2 * 2 * 3);
+        assert_eq!(m.positions[4], [1.0, 8.0, 1.0]);
+
+        // All triangles face upward
+        for t in m.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| m.positions[t[i] as usize]);
+            let (u, v) = ([b[0] - a[0], b[2] - a[2]], [c[0] - a[0], c[2] - a[2]]);
+            // Y component of cross product (b - a) x (c - a)
+            assert!(u[1] * v[0] - u[0] * v[1] > 0.0, "{:?}", t);
+        }
+
+        let m = Mesh::new(&Board::build(1, 3, |_, _| LandKind::Sea.preset(0)), 1.0);
+        assert!(m.indices.is_empty());
+    }
+
+    #[test]
+    fn write_obj_file() {
+        let mut buf = Vec::new();
+        Mesh::new(&board(), 1.0).write_obj(&mut buf).unwrap();
+        let obj = String::from_utf8(buf).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 6);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 4);
+        assert!(obj.contains("\nv 2 2 0 "), "{}", obj);
+        assert!(obj.contains("\nf 1 4 2\n"), "{}", obj);
+    }
+
+    #[test]
+    fn write_glb_file() {
+        let mut buf = Vec::new();
+        Mesh::new(&board(), 1.0).write_glb(&mut buf).unwrap();
+
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        assert_eq!(u32_at(0), GLB_MAGIC);
+        assert_eq!(u32_at(4), 2);
+        assert_eq!(u32_at(8) as usize, buf.len());
+        assert_eq!(buf.len() % 4, 0);
+
+        let json_len = u32_at(12) as usize;
+        assert_eq!(u32_at(16), CHUNK_JSON);
+        let json: serde_json::Value = serde_json::from_slice(&buf[20..20 + json_len]).unwrap();
+        assert_eq!(json["accessors"][0]["count"], 6);
+        assert_eq!(json["accessors"][2]["count"], 12);
+
+        let bin_len = u32_at(20 + json_len) as usize;
+        assert_eq!(u32_at(24 + json_len), CHUNK_BIN);
+        assert_eq!(bin_len, 6 * 12 * 2 + 12 * 4);
+        assert_eq!(json["buffers"][0]["byteLength"], bin_len);
+
+        for &(w, h) in &[(0, 0), (1, 3), (4, 1)] {
+            let board = Board::build(w, h, |_, _| LandKind::Plain.preset(10));
+            match Mesh::new(&board, 1.0).write_glb(&mut Vec::new()) {
+                Err(Error::CannotEncodeGltf(_)) => {}
+                r => panic!("Unexpected result {:?} for {}x{}", r, w, h),
+            }
+        }
+    }
+}
@@ -2,9 +2,12 @@ use rand;
 
 use self::rand::seq::SliceRandom;
 use self::rand::Rng;
+use crate::biome::{sea_level_for_water_ratio, BiomeTable};
 use crate::board::{Board, Pos};
+use crate::climate::Climate;
+use crate::gen::{GenConfig, Resolution};
 use crate::land::LandKind;
-use crate::slope::SlopeGen;
+use crate::terrain::{highest_peaks, Heightmap};
 use std::collections::HashSet;
 
 pub struct MiddleBoardGen<'a, R: Rng> {
@@ -15,16 +18,26 @@ pub struct MiddleBoardGen<'a, R: Rng> {
     min_distance: usize,
     down_rate: u8,
     num_tops: usize,
+    biomes: BiomeTable,
+    config: &'a GenConfig,
 }
 
 impl<'a, R: Rng> MiddleBoardGen<'a, R> {
-    pub fn new<'b: 'a>(rng: &'b mut R, width: usize, height: usize) -> Self {
-        let num_towns = width * height / 2048 + rng.gen_range(1, 4);
+    pub fn new<'b: 'a>(rng: &'b mut R, width: usize, height: usize, config: &'b GenConfig) -> Self {
+        let num_towns = config
+            .num_towns
+            .unwrap_or_else(|| width * height / 2048 + rng.gen_range(1, 4));
         let both = width + height;
-        let min_distance = both.checked_div(num_towns).unwrap_or(both);
+        let min_distance = config
+            .min_distance
+            .unwrap_or_else(|| both.checked_div(num_towns).unwrap_or(both));
         // Note: Standard value is 20 at 48x36 board
-        let down_rate = 12 + (48 * 36 * 8 / (width * height)) as u8;
-        let num_tops = 3 + both * rng.gen_range(3, 7) / (48 + 36);
+        let down_rate = config
+            .down_rate
+            .unwrap_or_else(|| 12 + (48 * 36 * 8 / (width * height)) as u8);
+        let num_tops = config
+            .num_tops
+            .unwrap_or_else(|| 3 + both * rng.gen_range(3, 7) / (48 + 36));
 
         MiddleBoardGen {
             rng,
@@ -34,36 +47,66 @@ impl<'a, R: Rng> MiddleBoardGen<'a, R> {
             min_distance,
             down_rate,
             num_tops,
+            biomes: config.biome_table(Resolution::Middle),
+            config,
         }
     }
 
-    #[inline]
-    fn land_kind(altitude: u8) -> LandKind {
-        match altitude {
-            0..=10 => LandKind::Sea,
-            11..=40 => LandKind::Plain,
-            41..=70 => LandKind::Forest,
-            71..=99 => LandKind::Mountain,
-            _ => unreachable!(),
-        }
-    }
-
-    pub fn gen(&mut self) -> Board<'static> {
-        let mut slope = SlopeGen::new(
+    pub fn gen(&mut self) -> Board {
+        let heightmap = self.config.terrain.gen(
             self.rng,
             self.width,
             self.height,
             self.down_rate,
             self.num_tops,
+            self.config.wrap,
+            self.config.layout,
         );
-        slope.gen();
-        let altitudes = slope.altitudes;
-        let tops = slope.tops;
+        self.gen_on(heightmap)
+    }
+
+    // Generate a board from the given altitudes. Tops of mountains are chosen from the highest
+    // local maxima.
+    pub fn gen_from_altitudes(&mut self, altitudes: Vec<Vec<u8>>) -> Board {
+        let tops = highest_peaks(&altitudes, self.num_tops, self.config.wrap);
+        self.gen_on(Heightmap { altitudes, tops })
+    }
+
+    fn gen_on(&mut self, heightmap: Heightmap) -> Board {
+        let Heightmap {
+            mut altitudes,
+            tops,
+        } = heightmap;
+
+        if let Some(erosion) = &self.config.erosion {
+            erosion.erode(self.rng, &mut altitudes, self.config.wrap);
+        }
+
+        if let Some(ratio) = self.config.water_ratio {
+            let level = sea_level_for_water_ratio(altitudes.iter().flatten().cloned(), ratio);
+            self.biomes = self.biomes.with_sea_level(level);
+        }
+
+        let (wrap, layout) = (self.config.wrap, self.config.layout);
+        let neighborhood = self.config.neighborhood;
+        let climate = self
+            .config
+            .climate
+            .as_ref()
+            .map(|params| Climate::new(params, &altitudes, self.biomes.sea_level(), wrap));
+        let biomes = &self.biomes;
+        let land_kind = |x: usize, y: usize| {
+            let kind = biomes.land_kind(altitudes[y][x]);
+            match &climate {
+                Some(climate) => climate.classify(kind, x, y),
+                None => kind,
+            }
+        };
 
         let mut plains = Vec::new();
         for (h, line) in altitudes.iter().enumerate() {
-            for (w, alt) in line.iter().enumerate() {
-                if Self::land_kind(*alt) == LandKind::Plain {
+            for w in 0..line.len() {
+                if matches!(land_kind(w, h), LandKind::Plain | LandKind::Savanna) {
                     plains.push(Pos { x: w, y: h });
                 }
             }
@@ -77,16 +120,16 @@ impl<'a, R: Rng> MiddleBoardGen<'a, R> {
             if towns.len() == self.num_towns {
                 break;
             }
-            if towns
-                .iter()
-                .all(|p: &Pos| p.move_cost(g) > self.min_distance)
-            {
+            if towns.iter().all(|p: &Pos| {
+                let cost = layout.move_cost(p, g, wrap, neighborhood, self.width, self.height);
+                cost > self.min_distance
+            }) {
                 towns.insert(*g);
             }
         }
         let towns = towns;
 
-        Board::build(self.width, self.height, |w, h| {
+        let mut board = Board::build(self.width, self.height, |w, h| {
             let alt = altitudes[h][w];
             let p = Pos { x: w, y: h };
             if tops.contains(&p) {
@@ -94,8 +137,10 @@ impl<'a, R: Rng> MiddleBoardGen<'a, R> {
             } else if towns.contains(&p) {
                 LandKind::Town.preset(alt)
             } else {
-                Self::land_kind(alt).preset(alt)
+                land_kind(w, h).preset(alt)
             }
-        })
+        });
+        board.set_layout(layout);
+        board
     }
 }
@@ -0,0 +1,148 @@
+use rand;
+
+use self::rand::seq::SliceRandom;
+use self::rand::Rng;
+
+// Improved Perlin noise in 2D. Permutation table is shuffled by given random number generator
+// so that generated noise is deterministic under a seeded random number generator.
+pub struct Perlin {
+    perm: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let mut table = [0u8; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        table.shuffle(rng);
+
+        let mut perm = [0u8; 512];
+        for (i, v) in perm.iter_mut().enumerate() {
+            *v = table[i % 256];
+        }
+        Perlin { perm }
+    }
+
+    #[inline]
+    fn fade(t: f64) -> f64 {
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+    }
+
+    #[inline]
+    fn lerp(t: f64, a: f64, b: f64) -> f64 {
+        a + t * (b - a)
+    }
+
+    #[inline]
+    fn grad(hash: u8, x: f64, y: f64) -> f64 {
+        match hash & 7 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            3 => -x - y,
+            4 => x,
+            5 => -x,
+            6 => y,
+            _ => -y,
+        }
+    }
+
+    // Returns noise value at (x, y) in about -1.0..1.0
+    pub fn noise(&self, x: f64, y: f64) -> f64 {
+        let (fx, fy) = (x.floor(), y.floor());
+        let xi = (fx as i64 & 255) as usize;
+        let yi = (fy as i64 & 255) as usize;
+        let (x, y) = (x - fx, y - fy);
+        let (u, v) = (Self::fade(x), Self::fade(y));
+
+        let p = &self.perm;
+        let a = p[xi] as usize + yi;
+        let b = p[xi + 1] as usize + yi;
+
+        Self::lerp(
+            v,
+            Self::lerp(u, Self::grad(p[a], x, y), Self::grad(p[b], x - 1.0, y)),
+            Self::lerp(
+                u,
+                Self::grad(p[a + 1], x, y - 1.0),
+                Self::grad(p[b + 1], x - 1.0, y - 1.0),
+            ),
+        )
+    }
+}
+
+// Fractal Brownian motion which sums up octaves of Perlin noise
+pub struct Fbm {
+    perlin: Perlin,
+    offsets: Vec<(f64, f64)>,
+    lacunarity: f64,
+    persistence: f64,
+}
+
+impl Fbm {
+    pub fn new<R: Rng>(rng: &mut R, octaves: u32, lacunarity: f64, persistence: f64) -> Self {
+        let perlin = Perlin::new(rng);
+        // Shift each octave randomly not to align lattice points of octaves at origin
+        let offsets = (0..octaves)
+            .map(|_| (rng.gen_range(0.0, 256.0), rng.gen_range(0.0, 256.0)))
+            .collect();
+        Fbm {
+            perlin,
+            offsets,
+            lacunarity,
+            persistence,
+        }
+    }
+
+    pub fn noise(&self, x: f64, y: f64) -> f64 {
+        let mut sum = 0.0;
+        let mut freq = 1.0;
+        let mut amp = 1.0;
+        for (dx, dy) in self.offsets.iter() {
+            sum += amp * self.perlin.noise(x * freq + dx, y * freq + dy);
+            freq *= self.lacunarity;
+            amp *= self.persistence;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_zero_at_lattice_points() {
+        let p = Perlin::new(&mut rand::thread_rng());
+        for i in 0..10 {
+            let v = p.noise(f64::from(i), f64::from(i * 3));
+            assert!(v.abs() < 1e-9, "{}", v);
+        }
+    }
+
+    #[test]
+    fn perlin_is_continuous() {
+        let p = Perlin::new(&mut rand::thread_rng());
+        let mut prev = p.noise(0.0, 0.5);
+        for i in 1..1000 {
+            let v = p.noise(f64::from(i) * 0.01, 0.5);
+            assert!((-1.0..=1.0).contains(&v), "{}", v);
+            assert!((v - prev).abs() < 0.1, "{} -> {}", prev, v);
+            prev = v;
+        }
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        use rand::SeedableRng;
+        let mut r1 = rand::rngs::StdRng::seed_from_u64(7);
+        let mut r2 = rand::rngs::StdRng::seed_from_u64(7);
+        let f1 = Fbm::new(&mut r1, 4, 2.0, 0.5);
+        let f2 = Fbm::new(&mut r2, 4, 2.0, 0.5);
+        for i in 0..100 {
+            let (x, y) = (f64::from(i) * 0.37, f64::from(i) * 0.11);
+            assert_eq!(f1.noise(x, y), f2.noise(x, y));
+        }
+    }
+}
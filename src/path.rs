@@ -0,0 +1,669 @@
+//! This module provides path finding on boards. It is useful to move units on generated maps in
+//! games.
+//!
+//! `PathFinder` searches the cheapest route between cells of a board with A* algorithm. The cost
+//! of a route is the sum of costs to enter its cells except for the start. Costs are given by a
+//! function per land. `land_cost()` is used by default, which is the same table as the generator
+//! uses for building paths between towns.
+//!
+//! Cells are moved to their neighbors in the layout of the board. On `Neighborhood::Eight`,
+//! diagonal steps cost `DIAGONAL_STEP / STRAIGHT_STEP` times as much as straight steps and never
+//! cut a corner of water or impassable cells. Wrap mode and neighborhood are taken from metadata
+//! of the board when it has them.
+//!
+//! ```rust
+//! use world_map_gen::board::Pos;
+//! use world_map_gen::gen::RandomBoardGen;
+//! use world_map_gen::path::PathFinder;
+//!
+//! let board = RandomBoardGen::from_seed(42).gen_large(200, 150);
+//!
+//! // Units cannot enter sea in this game
+//! let mut finder = PathFinder::new(&board).with_cost(|land| {
+//!     if land.kind.is_water() {
+//!         None
+//!     } else {
+//!         Some(1 + land.altitude as usize / 10)
+//!     }
+//! });
+//!
+//! let (from, to) = (Pos { x: 10, y: 20 }, Pos { x: 150, y: 100 });
+//! if let Some(route) = finder.find(&from, &to) {
+//!     assert_eq!(route.cells.first(), Some(&from));
+//!     assert_eq!(route.cells.last(), Some(&to));
+//!     println!("Cost: {}", route.cost);
+//! }
+//!
+//! // Cells which a unit can reach with 30 move points
+//! let reachable = finder.reachable_within(&from, 30);
+//! ```
+
+use crate::board::{Board, Layout, Neighborhood, Pos, Wrap};
+use crate::land::{Land, LandKind};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::slice;
+
+/// Multiplier of costs of straight steps on `Neighborhood::Eight`.
+pub const STRAIGHT_STEP: usize = 5;
+/// Multiplier of costs of diagonal steps on `Neighborhood::Eight`. The ratio to `STRAIGHT_STEP`
+/// approximates sqrt(2) so that diagonal steps are not cheaper than straight ones.
+pub const DIAGONAL_STEP: usize = 7;
+
+// Cost of plains, towns and paths
+pub(crate) const MIN_LAND_COST: usize = 1;
+
+#[inline]
+pub(crate) fn kind_cost(kind: LandKind) -> usize {
+    match kind {
+        LandKind::DeepSea => 64,
+        LandKind::Sea | LandKind::Lake => 32,
+        LandKind::River => 24, // Need to build a bridge
+        LandKind::Plain | LandKind::Savanna => 1,
+        LandKind::Desert | LandKind::Tundra => 2,
+        LandKind::Forest => 4,
+        LandKind::Jungle | LandKind::Swamp => 6,
+        LandKind::Mountain => 8,
+        LandKind::Highland | LandKind::Top | LandKind::Snow => 16,
+        LandKind::Town | LandKind::Path => 1,
+    }
+}
+
+/// Default cost to enter a cell of the land. Plains, towns and paths are the cheapest and water
+/// is the most expensive. All lands can be entered.
+pub fn land_cost(land: &Land) -> Option<usize> {
+    Some(kind_cost(land.kind))
+}
+
+/// A route found by `PathFinder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// Cells of the route from the start to the goal. Both are included.
+    pub cells: Vec<Pos>,
+    /// Total cost of the route
+    pub cost: usize,
+}
+
+// Shape of a board and how to move on it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub layout: Layout,
+    pub wrap: Wrap,
+    pub neighborhood: Neighborhood,
+}
+
+impl Grid {
+    #[inline]
+    fn index(&self, p: &Pos) -> usize {
+        p.y * self.width + p.x
+    }
+
+    #[inline]
+    fn pos(&self, index: usize) -> Pos {
+        Pos {
+            x: index % self.width,
+            y: index / self.width,
+        }
+    }
+
+    #[inline]
+    fn contains(&self, p: &Pos) -> bool {
+        p.x < self.width && p.y < self.height
+    }
+
+    // Steps are multiplied only when diagonal steps are allowed
+    #[inline]
+    fn has_diagonal(&self) -> bool {
+        self.layout == Layout::Square && self.neighborhood == Neighborhood::Eight
+    }
+
+    #[inline]
+    fn min_step(&self) -> usize {
+        if self.has_diagonal() {
+            STRAIGHT_STEP
+        } else {
+            1
+        }
+    }
+}
+
+// Vertex of path search ordered by estimated total cost for priority queue. When estimates are
+// the same, a vertex with higher cost, which is closer to the goal, comes first.
+#[derive(PartialEq, Eq)]
+struct Vert {
+    estimate: usize,
+    cost: usize,
+    index: usize,
+}
+
+impl Ord for Vert {
+    fn cmp(&self, rhs: &Vert) -> Ordering {
+        rhs.estimate
+            .cmp(&self.estimate)
+            .then(self.cost.cmp(&rhs.cost))
+            .then(rhs.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Vert {
+    fn partial_cmp(&self, rhs: &Vert) -> Option<Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+// Buffers of path search indexed by cells from left-top to right-bottom. They are allocated once
+// per board and reused for all searches since allocating them for each search is slow on large
+// boards. Only visited cells are reset before each search.
+pub(crate) struct Search {
+    grid: Grid,
+    costs: Vec<usize>,
+    parents: Vec<usize>,
+    goals: Vec<bool>,
+    visited: Vec<usize>,
+    queue: BinaryHeap<Vert>,
+}
+
+impl Search {
+    pub(crate) fn new(grid: Grid) -> Search {
+        let num_cells = grid.width * grid.height;
+        Search {
+            grid,
+            costs: vec![usize::MAX; num_cells],
+            parents: vec![0; num_cells],
+            goals: vec![false; num_cells],
+            visited: Vec::new(),
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        for &i in self.visited.iter() {
+            self.costs[i] = usize::MAX;
+        }
+        self.visited.clear();
+        self.queue.clear();
+    }
+
+    #[inline]
+    fn visit(&mut self, index: usize, cost: usize, parent: usize) {
+        if self.costs[index] == usize::MAX {
+            self.visited.push(index);
+        }
+        self.costs[index] = cost;
+        self.parents[index] = parent;
+    }
+
+    // Search cells from the start in order of estimated total cost with A* algorithm until one of
+    // the goals is reached, and returns the reached goal. `cost` returns the cost to enter a cell
+    // or `None` when the cell cannot be entered. Diagonal steps never pass cells for which
+    // `blocks_corner` returns true. `min_cost` must not be greater than any cost so that the
+    // heuristic never overestimates. Cells whose costs exceed `budget` are not visited. When
+    // `goals` is empty, all cells within the budget are visited with Dijkstra's algorithm. Goals
+    // must be on the board.
+    pub(crate) fn run(
+        &mut self,
+        start: &Pos,
+        goals: &[Pos],
+        min_cost: usize,
+        budget: usize,
+        cost: impl Fn(&Pos) -> Option<usize>,
+        blocks_corner: impl Fn(&Pos) -> bool,
+    ) -> Option<Pos> {
+        let grid = self.grid;
+        let unit = min_cost * grid.min_step();
+        // Estimating the distance to the nearest of many goals costs O(goals) per cell. So the
+        // heuristic is only used for a single goal and multiple goals are searched with Dijkstra's
+        // algorithm.
+        let target = match goals {
+            [goal] => Some(goal),
+            _ => None,
+        };
+        let heuristic = |p: &Pos| match target {
+            Some(g) => {
+                let steps = grid.layout.move_cost(
+                    p,
+                    g,
+                    grid.wrap,
+                    grid.neighborhood,
+                    grid.width,
+                    grid.height,
+                );
+                steps.saturating_mul(unit)
+            }
+            None => 0,
+        };
+
+        self.reset();
+        for g in goals {
+            self.goals[grid.index(g)] = true;
+        }
+        let start_index = grid.index(start);
+        self.visit(start_index, 0, start_index);
+        self.queue.push(Vert {
+            estimate: heuristic(start),
+            cost: 0,
+            index: start_index,
+        });
+
+        let mut reached = None;
+        while let Some(Vert { cost: c, index, .. }) = self.queue.pop() {
+            if c > self.costs[index] {
+                // Outdated entry. The cell was already reached with lower cost
+                continue;
+            }
+
+            let from = grid.pos(index);
+            if self.goals[index] {
+                reached = Some(from);
+                break;
+            }
+
+            for &(dx, dy) in grid.layout.directions(&from, grid.neighborhood) {
+                let offset = |dx, dy| grid.wrap.offset(&from, dx, dy, grid.width, grid.height);
+                let pos = match offset(dx, dy) {
+                    Some(p) => p,
+                    None => continue,
+                };
+
+                let diagonal = grid.layout == Layout::Square && dx != 0 && dy != 0;
+                if diagonal
+                    && [offset(dx, 0), offset(0, dy)]
+                        .iter()
+                        .any(|p| p.as_ref().map(&blocks_corner).unwrap_or(false))
+                {
+                    // Cutting a corner is not allowed
+                    continue;
+                }
+
+                let step = match (diagonal, grid.has_diagonal()) {
+                    (true, _) => DIAGONAL_STEP,
+                    (false, true) => STRAIGHT_STEP,
+                    (false, false) => 1,
+                };
+                let next_cost = match cost(&pos) {
+                    Some(cost) => c.saturating_add(cost.saturating_mul(step)),
+                    None => continue,
+                };
+                let next = grid.index(&pos);
+                if next_cost > budget || next_cost >= self.costs[next] {
+                    continue;
+                }
+
+                self.visit(next, next_cost, index);
+                self.queue.push(Vert {
+                    estimate: next_cost.saturating_add(heuristic(&pos)),
+                    cost: next_cost,
+                    index: next,
+                });
+            }
+        }
+
+        for g in goals {
+            self.goals[grid.index(g)] = false;
+        }
+        reached // `None` when goals are unreachable
+    }
+
+    // Cells of the route from the start to the goal found by the last search
+    pub(crate) fn route(&self, start: &Pos, goal: &Pos) -> Vec<Pos> {
+        let start = self.grid.index(start);
+        let mut index = self.grid.index(goal);
+        let mut cells = vec![self.grid.pos(index)];
+        while index != start {
+            index = self.parents[index];
+            cells.push(self.grid.pos(index));
+        }
+        cells.reverse();
+        cells
+    }
+
+    #[inline]
+    fn cost(&self, p: &Pos) -> usize {
+        self.costs[self.grid.index(p)]
+    }
+}
+
+/// A path finder on a board. It searches the cheapest routes between cells with A* algorithm.
+/// Buffers for searching are allocated once and reused for each query so that many queries on
+/// the same board are efficient. Please see the module document for the details.
+pub struct PathFinder<'a, F = fn(&Land) -> Option<usize>> {
+    board: &'a Board,
+    cost: F,
+    search: Search,
+    min_cost: Option<usize>,
+}
+
+impl<'a> PathFinder<'a> {
+    /// Creates a path finder on the board with the default costs by `land_cost()`. Wrap mode and
+    /// neighborhood are taken from metadata of the board. When the board has no metadata, edges
+    /// are not connected and 4 directions are allowed to move on square layout.
+    pub fn new(board: &'a Board) -> PathFinder<'a> {
+        let (wrap, neighborhood) = board
+            .metadata()
+            .map(|m| (m.config.wrap, m.config.neighborhood))
+            .unwrap_or_default();
+        let grid = Grid {
+            width: board.width(),
+            height: board.height(),
+            layout: board.layout(),
+            wrap,
+            neighborhood,
+        };
+        PathFinder {
+            board,
+            cost: land_cost,
+            search: Search::new(grid),
+            min_cost: None,
+        }
+    }
+}
+
+impl<'a, F: Fn(&Land) -> Option<usize>> PathFinder<'a, F> {
+    /// Replaces the cost function. It returns the cost to enter a cell of the land, or `None` when
+    /// the cell cannot be entered.
+    pub fn with_cost<G: Fn(&Land) -> Option<usize>>(self, cost: G) -> PathFinder<'a, G> {
+        PathFinder {
+            board: self.board,
+            cost,
+            search: self.search,
+            min_cost: None,
+        }
+    }
+
+    /// Sets how the board wraps around at its edges.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.search.grid.wrap = wrap;
+        self
+    }
+
+    /// Sets cells to move to in one step on square layout.
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.search.grid.neighborhood = neighborhood;
+        self
+    }
+
+    // The lowest cost of all cells for the heuristic. It is calculated only once since it visits
+    // all cells
+    fn min_cost(&mut self) -> usize {
+        if let Some(c) = self.min_cost {
+            return c;
+        }
+        let cost = &self.cost;
+        let c = self.board.iter().filter_map(cost).min().unwrap_or(0);
+        self.min_cost = Some(c);
+        c
+    }
+
+    fn run(&mut self, start: &Pos, goals: &[Pos], budget: usize) -> Option<Pos> {
+        let min_cost = self.min_cost();
+        let PathFinder {
+            board,
+            cost,
+            search,
+            ..
+        } = self;
+        let board = *board;
+        search.run(
+            start,
+            goals,
+            min_cost,
+            budget,
+            |p| cost(&board[*p]),
+            |p| {
+                let land = &board[*p];
+                land.kind.is_water() || land.kind.is_inland_water() || cost(land).is_none()
+            },
+        )
+    }
+
+    /// Finds the cheapest route from `from` to `to`. When `to` cannot be reached or either
+    /// position is out of the board, it returns `None`.
+    pub fn find(&mut self, from: &Pos, to: &Pos) -> Option<Route> {
+        self.find_nearest(from, slice::from_ref(to))
+    }
+
+    /// Finds the cheapest route from `from` to the nearest one of `goals`. The nearest goal is the
+    /// last cell of the route. Goals out of the board are ignored. When no goal can be reached, it
+    /// returns `None`. With multiple goals, cells are searched with Dijkstra's algorithm instead
+    /// of A* since estimating the distance to the nearest goal costs O(goals) per cell.
+    pub fn find_nearest(&mut self, from: &Pos, goals: &[Pos]) -> Option<Route> {
+        let grid = self.search.grid;
+        if !grid.contains(from) {
+            return None;
+        }
+        let goals = goals
+            .iter()
+            .filter(|g| grid.contains(g))
+            .cloned()
+            .collect::<Vec<_>>();
+        if goals.is_empty() {
+            return None;
+        }
+
+        let goal = self.run(from, &goals, usize::MAX)?;
+        Some(Route {
+            cells: self.search.route(from, &goal),
+            cost: self.search.cost(&goal),
+        })
+    }
+
+    /// Returns all cells which can be reached from `from` with total cost lower than or equal to
+    /// `budget`, and their cheapest costs. `from` is included with cost 0. When `from` is out of
+    /// the board, it returns an empty map.
+    pub fn reachable_within(&mut self, from: &Pos, budget: usize) -> HashMap<Pos, usize> {
+        if !self.search.grid.contains(from) {
+            return HashMap::new();
+        }
+        self.run(from, &[], budget);
+        let search = &self.search;
+        search
+            .visited
+            .iter()
+            .map(|&i| (search.grid.pos(i), search.costs[i]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::RandomBoardGen;
+
+    // Build a board from rows of characters. '.' is plain, 'f' is forest, '~' is sea and '#' is
+    // mountain
+    fn board(rows: &[&str]) -> Board {
+        let rows = rows.iter().map(|r| r.as_bytes()).collect::<Vec<_>>();
+        Board::build(rows[0].len(), rows.len(), |x, y| match rows[y][x] {
+            b'.' => LandKind::Plain.preset(60),
+            b'f' => LandKind::Forest.preset(75),
+            b'~' => LandKind::Sea.preset(45),
+            b'#' => LandKind::Mountain.preset(85),
+            c => panic!("unknown cell {}", c as char),
+        })
+    }
+
+    fn p(x: usize, y: usize) -> Pos {
+        Pos { x, y }
+    }
+
+    fn no_water(land: &Land) -> Option<usize> {
+        if land.kind.is_water() {
+            None
+        } else {
+            land_cost(land)
+        }
+    }
+
+    #[test]
+    fn find_cheapest_route() {
+        let b = board(&[
+            ".....", //
+            ".fff.", //
+            ".....", //
+        ]);
+        let mut finder = PathFinder::new(&b);
+        let route = finder.find(&p(0, 1), &p(4, 1)).unwrap();
+        assert_eq!(route.cells.first(), Some(&p(0, 1)));
+        assert_eq!(route.cells.last(), Some(&p(4, 1)));
+        assert_eq!(route.cells.len(), 7);
+        assert_eq!(route.cost, 6);
+        assert!(route.cells.iter().all(|c| b[*c].kind == LandKind::Plain));
+
+        // Adjacent cells are connected
+        for w in route.cells.windows(2) {
+            assert_eq!(w[0].move_cost(&w[1]), 1, "{:?}", w);
+        }
+
+        let route = finder.find(&p(2, 2), &p(2, 2)).unwrap();
+        assert_eq!(route.cells, vec![p(2, 2)]);
+        assert_eq!(route.cost, 0);
+    }
+
+    #[test]
+    fn impassable_cells() {
+        let b = board(&[
+            "..~..", //
+            "..~..", //
+            ".....", //
+        ]);
+        let mut finder = PathFinder::new(&b).with_cost(no_water);
+        let route = finder.find(&p(0, 0), &p(4, 0)).unwrap();
+        assert_eq!(route.cost, 8);
+        assert!(route.cells.contains(&p(2, 2)));
+
+        let b = board(&[
+            "..~..", //
+            "..~..", //
+        ]);
+        let mut finder = PathFinder::new(&b).with_cost(no_water);
+        assert_eq!(finder.find(&p(0, 0), &p(4, 0)), None);
+        assert_eq!(finder.find(&p(0, 0), &p(2, 0)), None);
+        assert_eq!(finder.find(&p(0, 0), &p(5, 0)), None);
+        assert_eq!(finder.find(&p(0, 9), &p(1, 0)), None);
+        assert!(finder.find(&p(0, 0), &p(1, 1)).is_some());
+
+        // Default cost allows crossing water
+        let route = PathFinder::new(&b).find(&p(0, 0), &p(4, 0)).unwrap();
+        assert_eq!(route.cost, 1 + 32 + 1 + 1);
+    }
+
+    #[test]
+    fn find_nearest_goal() {
+        let b = board(&[
+            "......", //
+            ".####.", //
+            "......", //
+        ]);
+        let mut finder = PathFinder::new(&b);
+        let goals = [p(5, 2), p(0, 2), p(9, 9)];
+        let route = finder.find_nearest(&p(0, 0), &goals).unwrap();
+        assert_eq!(route.cells.last(), Some(&p(0, 2)));
+        assert_eq!(route.cost, 2);
+        let route = finder.find_nearest(&p(5, 0), &goals).unwrap();
+        assert_eq!(route.cells.last(), Some(&p(5, 2)));
+        // Goals of previous searches are forgotten
+        let route = finder.find(&p(0, 0), &p(5, 2)).unwrap();
+        assert_eq!(route.cells.last(), Some(&p(5, 2)));
+        assert_eq!(route.cost, 7);
+        assert_eq!(finder.find_nearest(&p(0, 0), &[]), None);
+    }
+
+    #[test]
+    fn reachable_within_budget() {
+        let b = board(&[
+            "....", //
+            ".f~.", //
+            "....", //
+        ]);
+        let mut finder = PathFinder::new(&b).with_cost(no_water);
+        let reachable = finder.reachable_within(&p(0, 1), 2);
+        let mut cells = reachable.keys().cloned().collect::<Vec<_>>();
+        cells.sort_by_key(|c| (c.y, c.x));
+        assert_eq!(cells, vec![p(0, 0), p(1, 0), p(0, 1), p(0, 2), p(1, 2)]);
+        assert_eq!(reachable[&p(0, 1)], 0);
+        assert_eq!(reachable[&p(1, 2)], 2);
+
+        let reachable = finder.reachable_within(&p(0, 1), 4);
+        assert_eq!(reachable[&p(1, 1)], 4);
+        assert!(!reachable.contains_key(&p(2, 1)));
+        assert!(finder.reachable_within(&p(9, 9), 4).is_empty());
+    }
+
+    #[test]
+    fn diagonal_steps() {
+        let b = board(&[
+            "....", //
+            "....", //
+            "....", //
+            "....", //
+        ]);
+        let mut finder = PathFinder::new(&b).with_neighborhood(Neighborhood::Eight);
+        let route = finder.find(&p(0, 0), &p(3, 3)).unwrap();
+        assert_eq!(route.cells, (0..4).map(|i| p(i, i)).collect::<Vec<_>>());
+        assert_eq!(route.cost, DIAGONAL_STEP * 3);
+        let route = finder.find(&p(0, 0), &p(3, 0)).unwrap();
+        assert_eq!(route.cost, STRAIGHT_STEP * 3);
+
+        // Never cut a corner of water
+        let b = board(&[
+            ".~", //
+            "..", //
+        ]);
+        let mut finder = PathFinder::new(&b)
+            .with_cost(no_water)
+            .with_neighborhood(Neighborhood::Eight);
+        let route = finder.find(&p(0, 0), &p(1, 1)).unwrap();
+        assert_eq!(route.cells, vec![p(0, 0), p(0, 1), p(1, 1)]);
+    }
+
+    #[test]
+    fn route_across_wrapped_edges() {
+        let b = board(&[
+            "..ff..", //
+            "..ff..", //
+        ]);
+        let route = PathFinder::new(&b).find(&p(0, 0), &p(5, 0)).unwrap();
+        assert_eq!(route.cost, 1 + 4 + 4 + 1 + 1);
+        let mut finder = PathFinder::new(&b).with_wrap(Wrap::Horizontal);
+        let route = finder.find(&p(0, 0), &p(5, 0)).unwrap();
+        assert_eq!(route.cells, vec![p(0, 0), p(5, 0)]);
+        assert_eq!(route.cost, 1);
+    }
+
+    #[test]
+    fn route_on_hex_layout() {
+        let mut b = board(&[
+            "....", //
+            "....", //
+            "....", //
+            "....", //
+        ]);
+        b.set_layout(Layout::Hex);
+        let route = PathFinder::new(&b).find(&p(0, 0), &p(2, 3)).unwrap();
+        assert_eq!(route.cost, 4);
+        for w in route.cells.windows(2) {
+            let n = Layout::Hex.neighbors(&w[0], Wrap::None, Neighborhood::Four, 4, 4);
+            assert!(n.into_iter().any(|c| c == w[1]), "{:?}", w);
+        }
+    }
+
+    #[test]
+    fn same_cost_as_exhaustive_search() {
+        let b = RandomBoardGen::from_seed(3).gen_large(150, 120);
+        let mut finder = PathFinder::new(&b);
+        let from = p(10, 10);
+        let costs = finder.reachable_within(&from, usize::MAX);
+        assert_eq!(costs.len(), 150 * 120);
+        for &to in &[p(140, 110), p(75, 3), p(0, 119), p(11, 10)] {
+            let route = finder.find(&from, &to).unwrap();
+            assert_eq!(route.cost, costs[&to], "{:?}", to);
+            let sum: usize = route.cells[1..]
+                .iter()
+                .map(|c| land_cost(&b[*c]).unwrap())
+                .sum();
+            assert_eq!(route.cost, sum);
+        }
+    }
+}
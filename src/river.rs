@@ -0,0 +1,366 @@
+use crate::board::{Layout, Neighborhood, Pos, Wrap};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+// Neighbors of the position. Water does not flow diagonally on square layout
+fn neighbors(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    wrap: Wrap,
+    layout: Layout,
+) -> impl Iterator<Item = Pos> {
+    layout.neighbors(&Pos { x, y }, wrap, Neighborhood::Four, width, height)
+}
+
+// Fill pits up to their spill points with priority-flood algorithm. Flood starts from water cells
+// and cells at edges of the board, where water flows out of the board, and climbs up to the lowest
+// unvisited cell. Water never flows out across wrapped edges. Returns the filled level of each cell
+// and the cell from which the flood reached it. The start cells have no such cell.
+#[allow(clippy::type_complexity)]
+fn flood(
+    altitudes: &[Vec<u8>],
+    is_water: impl Fn(u8) -> bool,
+    wrap: Wrap,
+    layout: Layout,
+) -> (Vec<Vec<u8>>, Vec<Vec<Option<Pos>>>) {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let mut levels = altitudes.to_vec();
+    let mut visited = vec![vec![false; width]; height];
+    let mut parents = vec![vec![None; width]; height];
+
+    // Ordered by flood level, then position for deterministic result
+    let mut queue = BinaryHeap::new();
+    for (y, row) in altitudes.iter().enumerate() {
+        for (x, alt) in row.iter().enumerate() {
+            let edge = (!wrap.horizontal() && (x == 0 || x == width - 1))
+                || (!wrap.vertical() && (y == 0 || y == height - 1));
+            if is_water(*alt) || edge {
+                visited[y][x] = true;
+                queue.push(Reverse((*alt, y, x)));
+            }
+        }
+    }
+
+    while let Some(Reverse((level, y, x))) = queue.pop() {
+        for p in neighbors(x, y, width, height, wrap, layout) {
+            if visited[p.y][p.x] {
+                continue;
+            }
+            visited[p.y][p.x] = true;
+            let level = level.max(altitudes[p.y][p.x]);
+            levels[p.y][p.x] = level;
+            parents[p.y][p.x] = Some(Pos { x, y });
+            queue.push(Reverse((level, p.y, p.x)));
+        }
+    }
+
+    (levels, parents)
+}
+
+// Calculate where water on each cell flows next. Water on each cell flows to its lowest neighbor
+// on the filled surface. On flat surface such as a filled pit, water flows to the cell from which
+// the flood reached it so that it eventually reaches the sea or an edge of the board. Water cells
+// and edges without lower neighbor have `None`.
+fn drainage(
+    altitudes: &[Vec<u8>],
+    is_water: impl Fn(u8) -> bool,
+    wrap: Wrap,
+    layout: Layout,
+) -> Vec<Vec<Option<Pos>>> {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let (levels, mut drains) = flood(altitudes, &is_water, wrap, layout);
+
+    // Prefer the steepest descent. Level strictly decreases on the descent and flood drains never
+    // go up, so following drains never loops.
+    for (y, row) in drains.iter_mut().enumerate() {
+        for (x, drain) in row.iter_mut().enumerate() {
+            if is_water(altitudes[y][x]) {
+                continue;
+            }
+            let level = levels[y][x];
+            if let Some(lowest) = neighbors(x, y, width, height, wrap, layout)
+                .filter(|p| levels[p.y][p.x] < level)
+                .min_by_key(|p| levels[p.y][p.x])
+            {
+                *drain = Some(lowest);
+            }
+        }
+    }
+
+    drains
+}
+
+// Find lakes. Pits filled by flood are lakes. Lakes whose number of cells is smaller than
+// `min_area` are ignored.
+pub fn lakes(
+    altitudes: &[Vec<u8>],
+    is_water: impl Fn(u8) -> bool,
+    min_area: usize,
+    wrap: Wrap,
+    layout: Layout,
+) -> HashSet<Pos> {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let (levels, _) = flood(altitudes, is_water, wrap, layout);
+    let is_filled = |p: Pos| levels[p.y][p.x] > altitudes[p.y][p.x];
+
+    let mut visited = vec![vec![false; width]; height];
+    let mut lakes = HashSet::new();
+    for y in 0..height {
+        for x in 0..width {
+            if visited[y][x] || !is_filled(Pos { x, y }) {
+                continue;
+            }
+
+            // Collect connected filled cells as one lake
+            let mut lake = Vec::new();
+            let mut stack = vec![Pos { x, y }];
+            visited[y][x] = true;
+            while let Some(p) = stack.pop() {
+                lake.push(p);
+                for n in neighbors(p.x, p.y, width, height, wrap, layout) {
+                    if !visited[n.y][n.x] && is_filled(n) {
+                        visited[n.y][n.x] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+
+            if lake.len() >= min_area {
+                lakes.extend(lake);
+            }
+        }
+    }
+
+    lakes
+}
+
+// Generate rivers which flow from the sources down to water. Each river follows the drainage and
+// ends when it reaches water, goes out of the board or joins another river. Rivers flow through
+// lakes and go out from their outlets. Altitudes on rivers are carved so that they never go up
+// toward their mouths. Source cells and lakes are not included in rivers.
+pub fn rivers(
+    altitudes: &mut [Vec<u8>],
+    sources: &[Pos],
+    lakes: &HashSet<Pos>,
+    is_water: impl Fn(u8) -> bool,
+    wrap: Wrap,
+    layout: Layout,
+) -> HashSet<Pos> {
+    let drains = drainage(altitudes, &is_water, wrap, layout);
+    let mut rivers = HashSet::new();
+
+    for source in sources {
+        let mut level = altitudes[source.y][source.x];
+        let mut next = drains[source.y][source.x];
+        while let Some(p) = next {
+            if rivers.contains(&p) || is_water(altitudes[p.y][p.x]) {
+                break;
+            }
+            next = drains[p.y][p.x];
+            if lakes.contains(&p) {
+                continue;
+            }
+            level = level.min(altitudes[p.y][p.x]);
+            altitudes[p.y][p.x] = level;
+            rivers.insert(p);
+        }
+    }
+
+    rivers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ps: &[(usize, usize)]) -> HashSet<Pos> {
+        ps.iter().map(|&(x, y)| Pos { x, y }).collect()
+    }
+
+    #[test]
+    fn flow_down_to_sea() {
+        let mut alts = vec![
+            vec![99, 99, 99, 99, 99, 99],
+            vec![0, 20, 30, 40, 50, 99],
+            vec![0, 40, 60, 70, 80, 99],
+            vec![0, 50, 20, 90, 95, 99],
+            vec![99, 99, 99, 99, 99, 99],
+        ];
+        let r = rivers(
+            &mut alts,
+            &[Pos { x: 4, y: 3 }],
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        // Flows to the lowest neighbor at each step
+        assert_eq!(r, set(&[(4, 2), (4, 1), (3, 1), (2, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn carve_through_pit() {
+        let mut alts = vec![
+            vec![99, 99, 99, 99, 99],
+            vec![99, 90, 10, 50, 0],
+            vec![99, 99, 99, 99, 99],
+        ];
+        let r = rivers(
+            &mut alts,
+            &[Pos { x: 1, y: 1 }],
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(2, 1), (3, 1)]));
+        // Altitude after the pit is carved
+        assert_eq!(alts[1], vec![99, 90, 10, 10, 0]);
+    }
+
+    #[test]
+    fn join_into_another_river() {
+        let mut alts = vec![
+            vec![99, 99, 99, 99, 99],
+            vec![99, 90, 80, 99, 99],
+            vec![99, 99, 70, 99, 99],
+            vec![99, 99, 60, 99, 99],
+            vec![99, 99, 0, 99, 99],
+        ];
+        let sources = [Pos { x: 2, y: 1 }, Pos { x: 1, y: 1 }];
+        let r = rivers(
+            &mut alts,
+            &sources,
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(2, 2), (2, 3), (2, 1)]));
+    }
+
+    #[test]
+    fn flow_out_of_board() {
+        let mut alts = vec![
+            vec![99, 99, 99, 99],
+            vec![99, 90, 80, 99],
+            vec![99, 99, 70, 99],
+            vec![99, 99, 30, 99],
+        ];
+        let r = rivers(
+            &mut alts,
+            &[Pos { x: 1, y: 1 }],
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(2, 1), (2, 2), (2, 3)]));
+    }
+
+    #[test]
+    fn flow_across_wrapped_edge() {
+        let alts = vec![
+            vec![99, 99, 99, 99, 99],
+            vec![30, 0, 99, 60, 40],
+            vec![99, 99, 99, 99, 99],
+        ];
+        let source = [Pos { x: 3, y: 1 }];
+        let r = rivers(
+            &mut alts.clone(),
+            &source,
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(4, 1)]));
+        let r = rivers(
+            &mut alts.clone(),
+            &source,
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::Horizontal,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(4, 1), (0, 1)]));
+    }
+
+    #[test]
+    fn flow_to_hex_neighbor() {
+        // Odd rows are shifted to the right on hex layout
+        let alts = vec![
+            vec![99, 99, 99, 99],
+            vec![99, 90, 99, 99],
+            vec![99, 99, 50, 99],
+            vec![99, 0, 99, 99],
+        ];
+        let source = [Pos { x: 1, y: 1 }];
+        let r = rivers(
+            &mut alts.clone(),
+            &source,
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Hex,
+        );
+        assert_eq!(r, set(&[(2, 2)]));
+        let r = rivers(
+            &mut alts.clone(),
+            &source,
+            &HashSet::new(),
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        // The pit is not adjacent to water and spills out of the board instead
+        assert_eq!(r, set(&[(1, 0)]));
+    }
+
+    #[test]
+    fn fill_pits_with_lakes() {
+        let alts = vec![
+            vec![0, 0, 0, 0, 0, 0, 0],
+            vec![0, 50, 50, 50, 50, 50, 0],
+            vec![0, 50, 10, 20, 50, 50, 0],
+            vec![0, 50, 10, 30, 50, 20, 50],
+            vec![0, 50, 50, 40, 50, 50, 0],
+            vec![0, 0, 0, 0, 0, 0, 0],
+        ];
+        let l = lakes(&alts, |a| a == 0, 1, Wrap::None, Layout::Square);
+        assert_eq!(l, set(&[(2, 2), (3, 2), (2, 3), (3, 3), (5, 3)]));
+
+        // Small lake is ignored
+        let l = lakes(&alts, |a| a == 0, 2, Wrap::None, Layout::Square);
+        assert_eq!(l, set(&[(2, 2), (3, 2), (2, 3), (3, 3)]));
+
+        // Basins spill out of the board at edges
+        let alts = vec![vec![10, 0, 10], vec![50, 50, 50]];
+        assert!(lakes(&alts, |_| false, 1, Wrap::None, Layout::Square).is_empty());
+    }
+
+    #[test]
+    fn flow_through_lake() {
+        let mut alts = vec![
+            vec![99, 99, 99, 99, 99, 99],
+            vec![99, 90, 10, 10, 30, 0],
+            vec![99, 99, 99, 99, 99, 99],
+        ];
+        let l = lakes(&alts, |a| a == 0, 1, Wrap::None, Layout::Square);
+        assert_eq!(l, set(&[(2, 1), (3, 1)]));
+        let r = rivers(
+            &mut alts,
+            &[Pos { x: 1, y: 1 }],
+            &l,
+            |a| a == 0,
+            Wrap::None,
+            Layout::Square,
+        );
+        assert_eq!(r, set(&[(4, 1)]));
+    }
+}
@@ -1,7 +1,7 @@
 use rand;
 
 use self::rand::Rng;
-use crate::board::Pos;
+use crate::board::{Layout, Neighborhood, Pos, Wrap};
 use std::cmp;
 use std::collections::HashSet;
 
@@ -13,15 +13,17 @@ pub struct SlopeGen<'a, R: Rng> {
     height: usize,
     down_rate: u8,
     num_tops: usize,
+    wrap: Wrap,
+    layout: Layout,
 }
 
-#[repr(u8)]
-enum Dir {
-    Above,
-    Below,
-    Left,
-    Right,
-}
+// Directions to visit neighbors as (dx, dy, index of down rate). Down rates are indexed by
+// direction so that each mountain has consistent slopes in each direction.
+const SQUARE_DIRS: [(isize, isize, usize); 4] = [(-1, 0, 2), (1, 0, 3), (0, -1, 0), (0, 1, 1)];
+
+// Max number of directions in all layouts
+const MAX_DIRS: usize = 6;
+type DownRates = [u8; MAX_DIRS];
 
 impl<'a, R: Rng> SlopeGen<'a, R> {
     pub fn new<'b: 'a>(
@@ -30,15 +32,10 @@ impl<'a, R: Rng> SlopeGen<'a, R> {
         height: usize,
         down_rate: u8,
         num_tops: usize,
+        wrap: Wrap,
+        layout: Layout,
     ) -> Self {
-        let mut altitudes = Vec::with_capacity(height);
-        for _ in 0..height {
-            let mut row = Vec::with_capacity(width);
-            for _ in 0..width {
-                row.push(0);
-            }
-            altitudes.push(row);
-        }
+        let altitudes = vec![vec![0; width]; height];
 
         // Too small num_tops causes infinite loop at .gen()
         let num_tops = cmp::min(num_tops, width * height);
@@ -50,57 +47,107 @@ impl<'a, R: Rng> SlopeGen<'a, R> {
             height,
             down_rate,
             num_tops,
+            wrap,
+            layout,
             tops: HashSet::with_capacity(num_tops),
         }
     }
 
-    // Down a slope
-    fn down(&mut self, altitude: u8, x: usize, y: usize, down_rates: [u8; 4], dir: Dir) {
-        let delta = self.rng.gen_range(0, down_rates[dir as usize]);
-        let altitude = altitude.saturating_sub(delta);
-        if self.altitudes[y][x] >= altitude {
-            // Skip when the altitude is already calculated as other mountain's slope
-            return;
+    // Create a slope of mountain. Cells are visited in depth-first order from the given position.
+    // An explicit stack is used instead of recursion since the depth of the search grows with
+    // the size of the board and it causes stack overflow on large boards.
+    fn slope(&mut self, altitude: u8, x: usize, y: usize, down_rates: DownRates) {
+        struct Frame {
+            altitude: u8,
+            x: usize,
+            y: usize,
+            next: usize, // Index of next direction to visit
         }
-        self.slope(altitude, x, y, down_rates);
-    }
 
-    // Create a slope of mountain
-    fn slope(&mut self, altitude: u8, x: usize, y: usize, down_rates: [u8; 4]) {
         self.altitudes[y][x] = altitude;
         if altitude == 0 {
             return;
         }
-        if x > 0 {
-            self.down(altitude, x - 1, y, down_rates, Dir::Left);
-        }
-        if self.width - 1 > x {
-            self.down(altitude, x + 1, y, down_rates, Dir::Right);
-        }
-        if y > 0 {
-            self.down(altitude, x, y - 1, down_rates, Dir::Above);
-        }
-        if self.height - 1 > y {
-            self.down(altitude, x, y + 1, down_rates, Dir::Below);
+
+        let mut stack = vec![Frame {
+            altitude,
+            x,
+            y,
+            next: 0,
+        }];
+
+        while let Some(frame) = stack.last_mut() {
+            let pos = Pos {
+                x: frame.x,
+                y: frame.y,
+            };
+            // Directions on hex layout are always in the same order so their indices are used
+            // as indices of down rates
+            let dir = match self.layout {
+                Layout::Square => SQUARE_DIRS.get(frame.next).copied(),
+                Layout::Hex => Layout::Hex
+                    .directions(&pos, Neighborhood::Four)
+                    .get(frame.next)
+                    .map(|&(dx, dy)| (dx, dy, frame.next)),
+            };
+            let (dx, dy, rate) = match dir {
+                Some(dir) => dir,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            frame.next += 1;
+
+            let altitude = frame.altitude;
+            let Pos { x, y } = match self.wrap.offset(&pos, dx, dy, self.width, self.height) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            // Down a slope
+            let delta = match down_rates[rate] {
+                0 => 0, // Very small down rate may be configured
+                rate => self.rng.gen_range(0, rate),
+            };
+            let altitude = altitude.saturating_sub(delta);
+            if self.altitudes[y][x] >= altitude {
+                // Skip when the altitude is already calculated as other mountain's slope
+                continue;
+            }
+
+            self.altitudes[y][x] = altitude;
+            if altitude > 0 {
+                stack.push(Frame {
+                    altitude,
+                    x,
+                    y,
+                    next: 0,
+                });
+            }
         }
     }
 
     // Generate down rates with 30% noise per direction
-    fn random_down_rates(&mut self) -> [u8; 4] {
-        let mut rates = [0, 0, 0, 0];
+    fn random_down_rates(&mut self) -> DownRates {
+        let mut rates = [0; MAX_DIRS];
+        let num_dirs = match self.layout {
+            Layout::Square => 4,
+            Layout::Hex => 6,
+        };
         let min = i32::from(self.down_rate) * 7 / 10;
         let deg = i32::from(self.down_rate) - min;
         if deg > 0 {
-            let mut budget = deg * 4;
-            for rate in rates.iter_mut().take(3) {
+            let mut budget = deg * num_dirs as i32;
+            for rate in rates.iter_mut().take(num_dirs - 1) {
                 let deg_max = cmp::min(budget, deg * 2 + 1);
                 if deg_max > 0 {
                     let noise = self.rng.gen_range(0, deg_max);
-                    *rate = (min as u8) + (noise as u8);
+                    *rate = (min as u8).saturating_add(noise as u8);
                     budget -= noise;
                 }
             }
-            rates[3] = (min as u8) + cmp::max(budget, 0) as u8;
+            rates[num_dirs - 1] = (min as u8).saturating_add(budget.clamp(0, 255) as u8);
         }
         rates
     }
@@ -121,9 +168,10 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::manual_range_contains)]
     fn generate_slope_invariant() {
         let mut rng = rand::thread_rng();
-        let mut slope = SlopeGen::new(&mut rng, 3, 4, 5, 2);
+        let mut slope = SlopeGen::new(&mut rng, 3, 4, 5, 2, Wrap::None, Layout::Square);
         slope.gen();
 
         let alt = slope.altitudes;
@@ -160,4 +208,52 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn generate_hex_slope() {
+        let mut rng = rand::thread_rng();
+        let (w, h) = (30, 20);
+        let mut slope = SlopeGen::new(&mut rng, w, h, 10, 3, Wrap::None, Layout::Hex);
+        slope.gen();
+
+        let alt = slope.altitudes;
+        for y in 0..h {
+            for x in 0..w {
+                let p = Pos { x, y };
+                if slope.tops.contains(&p) || alt[y][x] == 0 {
+                    continue;
+                }
+                // Slopes are propagated from one of the hex neighbors
+                assert!(
+                    Layout::Hex
+                        .neighbors(&p, Wrap::None, Neighborhood::Four, w, h)
+                        .any(|n| alt[n.y][n.x] >= alt[y][x]),
+                    "All hex neighbors are lower at {:?}",
+                    p
+                );
+            }
+        }
+
+        // Diagonal neighbors on hex layout are on a slope of the top
+        let top = *slope.tops.iter().next().unwrap();
+        let mut slope = SlopeGen::new(&mut rng, w, h, 10, 0, Wrap::Both, Layout::Hex);
+        slope.slope(99, top.x, top.y, [0; MAX_DIRS]);
+        for n in Layout::Hex.neighbors(&top, Wrap::Both, Neighborhood::Four, w, h) {
+            assert_eq!(slope.altitudes[n.y][n.x], 99, "{:?}", n);
+        }
+    }
+
+    #[test]
+    fn slope_across_edges() {
+        let mut rng = rand::thread_rng();
+        let (w, h) = (20, 10);
+        let mut slope = SlopeGen::new(&mut rng, w, h, 5, 1, Wrap::Both, Layout::Square);
+        slope.gen();
+
+        let top = *slope.tops.iter().next().unwrap();
+        for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let p = Wrap::Both.offset(&top, dx, dy, w, h).unwrap();
+            assert!(slope.altitudes[p.y][p.x] >= 95, "{:?}", p);
+        }
+    }
 }
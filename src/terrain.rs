@@ -0,0 +1,371 @@
+//! This module provides algorithms to generate altitudes of cells on middle and high resolution
+//! boards. An algorithm is selected with `gen::GenConfig`. Generated altitudes are classified into
+//! land kinds and then towns and paths are put in the same way regardless of the algorithm.
+//!
+//! ```rust
+//! use world_map_gen::gen::{GenConfig, RandomBoardGen};
+//! use world_map_gen::terrain::{NoiseParams, Terrain};
+//!
+//! let config = GenConfig {
+//!     terrain: Terrain::Noise(NoiseParams {
+//!         octaves: 4,
+//!         ..NoiseParams::default()
+//!     }),
+//!     ..GenConfig::default()
+//! };
+//! let board = RandomBoardGen::from_seed(42).with_config(config).gen_large(150, 150);
+//! assert_eq!(board.width(), 150);
+//! ```
+
+use rand;
+
+use self::rand::Rng;
+use crate::board::{Layout, Pos, Wrap};
+use crate::diamond_square::diamond_square;
+use crate::erosion::erode;
+use crate::noise::Fbm;
+use crate::slope::SlopeGen;
+use std::collections::HashSet;
+
+/// Parameters of fractal Brownian motion of Perlin noise. Some octaves of noise are summed up.
+/// Each octave has higher frequency and lower amplitude than previous one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseParams {
+    /// Number of octaves. More octaves make more detailed coastlines and mountains.
+    pub octaves: u32,
+    /// Multiplier of frequency per octave.
+    pub lacunarity: f64,
+    /// Multiplier of amplitude per octave. Larger value makes rougher terrain.
+    pub persistence: f64,
+    /// Size of the largest features such as continents in number of cells. When `None`, a half
+    /// of the longer side of the board is used.
+    pub scale: Option<f64>,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        NoiseParams {
+            octaves: 6,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            scale: None,
+        }
+    }
+}
+
+/// Parameters of diamond-square algorithm (midpoint displacement). Altitudes are generated on a
+/// grid tiled with squares whose side is the shorter side of the board rounded up to a power of
+/// two. The grid has up to about 4 times as many cells as the board and each of them takes 8 bytes
+/// while generating. Features of the terrain are not larger than the squares.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiamondSquareParams {
+    /// Multiplier of random displacement per subdivision step, usually in 0.0..1.0. Larger value
+    /// makes rougher terrain and more complicated coastlines.
+    pub roughness: f64,
+}
+
+impl Default for DiamondSquareParams {
+    fn default() -> Self {
+        DiamondSquareParams { roughness: 0.55 }
+    }
+}
+
+/// Parameters of erosion simulation applied to generated altitudes before classifying cells into
+/// land kinds. Hydraulic erosion simulates water droplets running down slopes, which carves valleys
+/// and deposits sediment on flat land. Then thermal erosion collapses slopes steeper than the talus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErosionParams {
+    /// Number of droplets of hydraulic erosion. When `None`, the number of cells of the board is
+    /// used.
+    pub droplets: Option<usize>,
+    /// Strength of hydraulic erosion in 0.0..=1.0. Larger value carves deeper valleys.
+    pub strength: f64,
+    /// Number of iterations of thermal erosion. 0 disables thermal erosion.
+    pub thermal_iterations: usize,
+    /// Max altitude difference between adjacent cells which is not collapsed by thermal erosion.
+    pub talus: f64,
+}
+
+impl Default for ErosionParams {
+    fn default() -> Self {
+        ErosionParams {
+            droplets: None,
+            strength: 0.3,
+            thermal_iterations: 10,
+            talus: 4.0,
+        }
+    }
+}
+
+impl ErosionParams {
+    // Erode altitudes in place. Random number generator is used for start points of droplets so
+    // the result is deterministic under a seeded generator.
+    pub(crate) fn erode<R: Rng>(&self, rng: &mut R, altitudes: &mut [Vec<u8>], wrap: Wrap) {
+        let cells = altitudes.iter().map(Vec::len).sum();
+        erode(
+            rng,
+            altitudes,
+            self.droplets.unwrap_or(cells),
+            self.strength.clamp(0.0, 1.0),
+            self.thermal_iterations,
+            self.talus.max(0.0),
+            wrap,
+        );
+    }
+}
+
+/// Algorithm to generate altitudes of cells.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Terrain {
+    /// Put tops of mountains at random positions and create random slopes around them. This is
+    /// the default algorithm.
+    Slope,
+    /// Coherent noise terrain by fractal Brownian motion of Perlin noise. Tops of mountains are
+    /// chosen from the highest local maxima.
+    Noise(NoiseParams),
+    /// Classic fractal terrain by diamond-square algorithm. Tops of mountains are chosen from the
+    /// highest local maxima.
+    DiamondSquare(DiamondSquareParams),
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::Slope
+    }
+}
+
+// Altitudes of cells in 0..=99 and positions of tops of mountains
+pub(crate) struct Heightmap {
+    pub altitudes: Vec<Vec<u8>>,
+    pub tops: HashSet<Pos>,
+}
+
+impl Terrain {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn gen<R: Rng>(
+        &self,
+        rng: &mut R,
+        width: usize,
+        height: usize,
+        down_rate: u8,
+        num_tops: usize,
+        wrap: Wrap,
+        layout: Layout,
+    ) -> Heightmap {
+        match self {
+            Terrain::Slope => {
+                let mut slope =
+                    SlopeGen::new(rng, width, height, down_rate, num_tops, wrap, layout);
+                slope.gen();
+                Heightmap {
+                    altitudes: slope.altitudes,
+                    tops: slope.tops,
+                }
+            }
+            Terrain::Noise(params) => {
+                let fbm = Fbm::new(rng, params.octaves, params.lacunarity, params.persistence);
+                // On wasm32, usize has 32bits length so converting from usize to f64 is lossless.
+                #[allow(clippy::cast_lossless)]
+                let scale = params
+                    .scale
+                    .unwrap_or_else(|| width.max(height) as f64 / 2.0)
+                    .max(1.0);
+                #[allow(clippy::cast_lossless)]
+                let (w, h) = (width as f64, height as f64);
+                let noise = |x: f64, y: f64| fbm.noise(x / scale, y / scale);
+                // Centers of cells in odd rows are shifted right by half a cell on hex layout
+                let shift = |y: usize| match layout {
+                    Layout::Hex if y % 2 == 1 => 0.5,
+                    _ => 0.0,
+                };
+                let values = (0..height)
+                    .map(|y| {
+                        (0..width)
+                            .map(|x| seamless(noise, x as f64 + shift(y), y as f64, w, h, wrap))
+                            .collect()
+                    })
+                    .collect();
+                let altitudes = normalize(values);
+                let tops = highest_peaks(&altitudes, num_tops, wrap);
+                Heightmap { altitudes, tops }
+            }
+            Terrain::DiamondSquare(params) => {
+                let values = diamond_square(rng, width, height, params.roughness, wrap);
+                let altitudes = normalize(values);
+                let tops = highest_peaks(&altitudes, num_tops, wrap);
+                Heightmap { altitudes, tops }
+            }
+        }
+    }
+}
+
+// Blend the value at (x, y) with values shifted by the size of the board on wrapped axes. Weights
+// change linearly along the axes so that values at the opposite edges become continuous.
+fn seamless(
+    f: impl Fn(f64, f64) -> f64,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    wrap: Wrap,
+) -> f64 {
+    match wrap {
+        Wrap::None => f(x, y),
+        Wrap::Horizontal => {
+            let u = x / width;
+            f(x, y) * (1.0 - u) + f(x - width, y) * u
+        }
+        Wrap::Both => {
+            let (u, v) = (x / width, y / height);
+            f(x, y) * (1.0 - u) * (1.0 - v)
+                + f(x - width, y) * u * (1.0 - v)
+                + f(x, y - height) * (1.0 - u) * v
+                + f(x - width, y - height) * u * v
+        }
+    }
+}
+
+// Scale values linearly into altitudes in 0..=99
+pub(crate) fn normalize(values: Vec<Vec<f64>>) -> Vec<Vec<u8>> {
+    let (min, max) = values
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        });
+    let range = max - min;
+    values
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|v| {
+                    if range > 0.0 {
+                        ((v - min) / range * 99.0).round() as u8
+                    } else {
+                        0
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Choose at most `num` local maxima of altitudes in descending order of altitude. Cells adjacent
+// to chosen ones are not chosen to avoid putting tops of mountain on the same plateau.
+pub(crate) fn highest_peaks(altitudes: &[Vec<u8>], num: usize, wrap: Wrap) -> HashSet<Pos> {
+    let height = altitudes.len();
+    let width = altitudes.first().map(Vec::len).unwrap_or(0);
+    let neighbors = |x: usize, y: usize| {
+        let p = Pos { x, y };
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter_map(move |(dx, dy)| wrap.offset(&p, dx, dy, width, height))
+            .filter(move |n| *n != p)
+            .map(|n| (n.x, n.y))
+    };
+
+    let mut peaks = Vec::new();
+    for (y, row) in altitudes.iter().enumerate() {
+        for (x, alt) in row.iter().enumerate() {
+            if neighbors(x, y).all(|(nx, ny)| altitudes[ny][nx] <= *alt) {
+                peaks.push((*alt, Pos { x, y }));
+            }
+        }
+    }
+    // Sort by altitude in descending order. Positions are compared for stable result.
+    peaks.sort_by(|(a1, p1), (a2, p2)| a2.cmp(a1).then((p1.y, p1.x).cmp(&(p2.y, p2.x))));
+
+    let mut tops = HashSet::with_capacity(num);
+    for (_, p) in peaks.into_iter() {
+        if tops.len() >= num {
+            break;
+        }
+        if neighbors(p.x, p.y).all(|(x, y)| !tops.contains(&Pos { x, y })) {
+            tops.insert(p);
+        }
+    }
+    tops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_values() {
+        let alts = normalize(vec![vec![-1.0, 0.0], vec![0.5, 1.0]]);
+        assert_eq!(alts, vec![vec![0, 50], vec![74, 99]]);
+        let alts = normalize(vec![vec![3.0, 3.0]]);
+        assert_eq!(alts, vec![vec![0, 0]]);
+        assert!(normalize(vec![]).is_empty());
+    }
+
+    #[test]
+    fn choose_highest_peaks() {
+        let alts = vec![
+            vec![9, 1, 1, 1, 1],
+            vec![1, 1, 1, 5, 1],
+            vec![1, 1, 1, 1, 1],
+            vec![7, 7, 1, 1, 3],
+        ];
+        let tops = highest_peaks(&alts, 3, Wrap::None);
+        let expected = [Pos { x: 0, y: 0 }, Pos { x: 0, y: 3 }, Pos { x: 3, y: 1 }];
+        assert_eq!(tops, expected.iter().cloned().collect());
+        assert_eq!(highest_peaks(&alts, 10, Wrap::None).len(), 4);
+        assert!(highest_peaks(&[], 3, Wrap::None).is_empty());
+
+        // Cells at the opposite edges are adjacent on wrapped board
+        let tops = highest_peaks(&alts, 10, Wrap::Both);
+        assert!(!tops.contains(&Pos { x: 4, y: 3 }), "{:?}", tops);
+        assert!(tops.contains(&Pos { x: 0, y: 0 }), "{:?}", tops);
+    }
+
+    #[test]
+    fn noise_terrain() {
+        let mut rng = rand::thread_rng();
+        let t = Terrain::Noise(NoiseParams::default());
+        let Heightmap { altitudes, tops } =
+            t.gen(&mut rng, 40, 30, 0, 5, Wrap::None, Layout::Square);
+        assert_eq!(altitudes.len(), 30);
+        assert!(altitudes.iter().all(|r| r.len() == 40));
+        assert!(altitudes.iter().flatten().all(|a| *a < 100));
+        assert!(altitudes.iter().flatten().any(|a| *a == 0));
+        assert!(altitudes.iter().flatten().any(|a| *a == 99));
+        assert!(!tops.is_empty() && tops.len() <= 5);
+        assert!(tops.contains(&{
+            let y = altitudes.iter().position(|r| r.contains(&99)).unwrap();
+            let x = altitudes[y].iter().position(|a| *a == 99).unwrap();
+            Pos { x, y }
+        }));
+    }
+
+    #[test]
+    fn erosion_keeps_altitude_range() {
+        let mut rng = rand::thread_rng();
+        let t = Terrain::Noise(NoiseParams::default());
+        let Heightmap { mut altitudes, .. } =
+            t.gen(&mut rng, 40, 30, 0, 5, Wrap::None, Layout::Square);
+        let before = altitudes.clone();
+        ErosionParams::default().erode(&mut rng, &mut altitudes, Wrap::None);
+        assert_ne!(before, altitudes);
+        assert_eq!(altitudes.len(), 30);
+        assert!(altitudes.iter().all(|r| r.len() == 40));
+        assert!(altitudes.iter().flatten().all(|a| *a < 100));
+    }
+
+    #[test]
+    fn diamond_square_terrain() {
+        let mut rng = rand::thread_rng();
+        let t = Terrain::DiamondSquare(DiamondSquareParams::default());
+        let Heightmap { altitudes, tops } =
+            t.gen(&mut rng, 50, 20, 0, 3, Wrap::None, Layout::Square);
+        assert_eq!(altitudes.len(), 20);
+        assert!(altitudes.iter().all(|r| r.len() == 50));
+        assert!(altitudes.iter().flatten().all(|a| *a < 100));
+        assert!(altitudes.iter().flatten().any(|a| *a == 99));
+        assert!(!tops.is_empty() && tops.len() <= 3);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::board::{Board, Layout};
+use crate::land::LandKind;
+use std::collections::BTreeSet;
+
+// Version of Tiled JSON map format
+const FORMAT_VERSION: &str = "1.10";
+// Number of altitudes. Each land kind has tiles for all altitudes.
+const ALTITUDES: usize = 100;
+// Empty tile in tile layers
+const EMPTY: u32 = 0;
+
+#[derive(Serialize)]
+struct Property {
+    name: &'static str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    value: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct Tile {
+    id: u32,
+    #[serde(rename = "type")]
+    class: &'static str,
+    properties: Vec<Property>,
+}
+
+#[derive(Serialize)]
+struct Tileset<'a> {
+    firstgid: u32,
+    name: &'static str,
+    image: &'a str,
+    imagewidth: usize,
+    imageheight: usize,
+    tilewidth: usize,
+    tileheight: usize,
+    tilecount: usize,
+    columns: usize,
+    margin: usize,
+    spacing: usize,
+    tiles: Vec<Tile>,
+}
+
+#[derive(Serialize)]
+struct Layer {
+    id: u32,
+    name: &'static str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    opacity: f64,
+    visible: bool,
+    data: Vec<u32>,
+}
+
+#[derive(Serialize)]
+pub struct Map<'a> {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    version: &'static str,
+    orientation: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    staggeraxis: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    staggerindex: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hexsidelength: Option<usize>,
+    renderorder: &'static str,
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tilewidth: usize,
+    tileheight: usize,
+    nextlayerid: u32,
+    nextobjectid: u32,
+    layers: Vec<Layer>,
+    tilesets: Vec<Tileset<'a>>,
+}
+
+// Local tile ID in the tileset. Tiles are arranged in a grid where each row is a land kind and
+// each column is an altitude.
+#[inline]
+fn tile_id(kind: LandKind, altitude: u8) -> u32 {
+    (kind as usize * ALTITUDES + usize::from(altitude.min(99))) as u32
+}
+
+// Towns and paths are put on a separate layer so that they can be edited independently
+#[inline]
+fn is_feature(kind: LandKind) -> bool {
+    kind == LandKind::Town || kind == LandKind::Path
+}
+
+// Size of tileset image in pixels
+pub fn tileset_size(tile_size: usize) -> (usize, usize) {
+    (ALTITUDES * tile_size, LandKind::ALL.len() * tile_size)
+}
+
+// Land kind and altitude of tile at the position in tileset image
+pub fn tileset_tile(x: usize, y: usize, tile_size: usize) -> (LandKind, u8) {
+    (LandKind::ALL[y / tile_size], (x / tile_size) as u8)
+}
+
+// Build Tiled map of the board. `tileset_image` is a path to the tileset image from the map file.
+pub fn map<'a>(board: &Board, tileset_image: &'a str, tile_size: usize) -> Map<'a> {
+    const FIRST_GID: u32 = 1;
+    let (width, height) = (board.width(), board.height());
+
+    let mut used = BTreeSet::new();
+    let mut terrain = Vec::with_capacity(width * height);
+    let mut features = Vec::with_capacity(width * height);
+    for cell in board.iter() {
+        if is_feature(cell.kind) {
+            // Underlying land of towns and paths is unknown. Towns are built on plains.
+            let id = tile_id(LandKind::Plain, cell.altitude);
+            terrain.push(FIRST_GID + id);
+            used.insert(id);
+            let id = tile_id(cell.kind, cell.altitude);
+            features.push(FIRST_GID + id);
+            used.insert(id);
+        } else {
+            let id = tile_id(cell.kind, cell.altitude);
+            terrain.push(FIRST_GID + id);
+            features.push(EMPTY);
+            used.insert(id);
+        }
+    }
+
+    // Properties are only defined for tiles used in the map to keep the file small
+    let tiles = used
+        .into_iter()
+        .map(|id| {
+            let kind = LandKind::ALL[id as usize / ALTITUDES];
+            let altitude = id as usize % ALTITUDES;
+            Tile {
+                id,
+                class: kind.legend(),
+                properties: vec![
+                    Property {
+                        name: "altitude",
+                        ty: "int",
+                        value: altitude.into(),
+                    },
+                    Property {
+                        name: "kind",
+                        ty: "string",
+                        value: format!("{:?}", kind).into(),
+                    },
+                ],
+            }
+        })
+        .collect();
+
+    // Hex cells are in odd-r offset coordinates; odd rows are shifted to the right by half a cell.
+    // Pointy-topped hex tiles overlap vertically by a quarter of their height.
+    let (orientation, staggeraxis, staggerindex, hexsidelength) = match board.layout() {
+        Layout::Square => ("orthogonal", None, None, None),
+        Layout::Hex => ("hexagonal", Some("y"), Some("odd"), Some(tile_size / 2)),
+    };
+
+    let (imagewidth, imageheight) = tileset_size(tile_size);
+    let layer = |id, name, data| Layer {
+        id,
+        name,
+        ty: "tilelayer",
+        width,
+        height,
+        x: 0,
+        y: 0,
+        opacity: 1.0,
+        visible: true,
+        data,
+    };
+
+    Map {
+        ty: "map",
+        version: FORMAT_VERSION,
+        orientation,
+        staggeraxis,
+        staggerindex,
+        hexsidelength,
+        renderorder: "right-down",
+        infinite: false,
+        width,
+        height,
+        tilewidth: tile_size,
+        tileheight: tile_size,
+        nextlayerid: 3,
+        nextobjectid: 1,
+        layers: vec![layer(1, "terrain", terrain), layer(2, "features", features)],
+        tilesets: vec![Tileset {
+            firstgid: FIRST_GID,
+            name: "lands",
+            image: tileset_image,
+            imagewidth,
+            imageheight,
+            tilewidth: tile_size,
+            tileheight: tile_size,
+            tilecount: ALTITUDES * LandKind::ALL.len(),
+            columns: ALTITUDES,
+            margin: 0,
+            spacing: 0,
+            tiles,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_ids_in_tileset_image() {
+        let size = 4;
+        let (w, h) = tileset_size(size);
+        for kind in LandKind::ALL.iter() {
+            for alt in &[0, 42, 99] {
+                let id = tile_id(*kind, *alt) as usize;
+                let (x, y) = (id % ALTITUDES * size, id / ALTITUDES * size);
+                assert!(x < w && y < h);
+                assert_eq!(tileset_tile(x + size - 1, y, size), (*kind, *alt));
+            }
+        }
+    }
+}
@@ -128,7 +128,7 @@ impl Cell {
 /// Represents one board generated by random map generator.
 #[wasm_bindgen]
 pub struct Board {
-    inner: board::Board<'static>,
+    inner: board::Board,
 }
 
 #[wasm_bindgen]
@@ -157,6 +157,12 @@ impl Board {
     pub fn as_json(&self) -> Option<String> {
         serde_json::to_string_pretty(&self.inner).ok()
     }
+
+    /// Returns a board by serializing as JSON in compact format of `board::CompactBoard`. On
+    /// failure, it returns `None` (`undefined` in JavaScript)
+    pub fn as_compact_json(&self) -> Option<String> {
+        serde_json::to_string(&board::CompactBoard(&self.inner)).ok()
+    }
 }
 
 /// Represents random map generator. In contrast to `gen::RandomBoardGen`, it only provides limited
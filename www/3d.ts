@@ -22,7 +22,7 @@ export default class Renderer3D implements Renderer {
         const point = new Point(this.canvas.width / 2, cellSize + 99 * 2);
         const pixelView = new PixelView(this.canvas, point);
 
-        const cache = new Map<number, Cube>(); // Altitude -> Cube
+        const cache = new Map<number, Cube>(); // Kind and altitude -> Cube
         const colors = new Map<number, CubeColor>(); // kind -> CubeColor
         const legends = new Map<number, Legend>(); // kind -> Legend
         let topCube: Cube | null = null;
@@ -68,17 +68,19 @@ export default class Renderer3D implements Renderer {
                 return topCube;
             }
 
-            if (kind === LandKind.Town || kind === LandKind.Path) {
+            if (kind === LandKind.Town || kind === LandKind.Path || kind === LandKind.River || kind === LandKind.Lake) {
                 return calcCube(kind, alt, cell);
             }
 
-            const cached = cache.get(alt);
+            // Cells at the same altitude may be different kinds (e.g. by climate)
+            const key = kind * 100 + alt;
+            const cached = cache.get(key);
             if (cached !== undefined) {
                 return cached;
             }
 
             const cube = calcCube(kind, alt, cell);
-            cache.set(alt, cube);
+            cache.set(key, cube);
             return cube;
         }
 